@@ -9,8 +9,6 @@ fn process_instruction(
     accounts: &[AccountInfo],
     instruction_data: &[u8],
 ) -> ProgramResult {
-    if let Err(error) = Processor::process(program_id, accounts, instruction_data) {
-        return Err(error);
-    }
+    Processor::process(program_id, accounts, instruction_data)?;
     Ok(())
 }
@@ -0,0 +1,62 @@
+use solana_program::program_error::ProgramError;
+use thiserror::Error;
+
+#[derive(Clone, Debug, Eq, Error, PartialEq)]
+pub enum TopicError {
+    #[error("Topic already has the maximum number of options")]
+    TopicFull,
+    #[error("Results are hidden until the topic is finished")]
+    ResultsHidden,
+    #[error("Options can no longer be changed once voting has started")]
+    OptionsLocked,
+    #[error("Voter account balance is below the topic minimum")]
+    VoterBalanceTooLow,
+    #[error("Topic has not reached its quorum")]
+    QuorumNotReached,
+    #[error("Topic is sealed and cannot be changed after finishing")]
+    TopicSealed,
+    #[error("Instruction was given more accounts than it uses")]
+    UnexpectedAccounts,
+    #[error("Option already has votes")]
+    OptionHasVotes,
+    #[error("Option has reached its voter limit")]
+    OptionFull,
+    #[error("Voter is banned from this topic")]
+    VoterBanned,
+    #[error("Token account has already backed a vote")]
+    TokenAccountUsed,
+    #[error("Voter is not on the topic's whitelist")]
+    VoterNotWhitelisted,
+    #[error("Instruction tag is not supported by this program")]
+    UnknownInstruction,
+    #[error("Voter has already used this nonce")]
+    NonceReused,
+    #[error("Voting deadline has passed")]
+    DeadlinePassed,
+    #[error("Voter has already voted on this topic")]
+    AlreadyVoted,
+    #[error("Topic is paused")]
+    TopicPaused,
+    #[error("Topic is already finished")]
+    AlreadyFinished,
+    #[error("Topic account is not owned by this program")]
+    WrongProgramOwner,
+    #[error("Signer is not the topic owner")]
+    NotTopicOwner,
+    #[error("Topic was changed too recently")]
+    TooSoon,
+    #[error("Token accounts backing a vote hold different mints")]
+    TokenMintMismatch,
+    #[error("Votes on this topic cannot be changed or retracted")]
+    VoteChangesDisabled,
+    #[error("Oracle account is not a weight for this voter from the topic's oracle")]
+    OracleMismatch,
+    #[error("Signer is not the program admin")]
+    NotAdmin,
+}
+
+impl From<TopicError> for ProgramError {
+    fn from(e: TopicError) -> Self {
+        ProgramError::Custom(e as u32)
+    }
+}
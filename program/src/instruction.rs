@@ -1,23 +1,145 @@
-use crate::util::str_pack;
+use crate::error::TopicError;
+use crate::state::{OptIdx, TopicConfig, MAX_OPTIONS, MAX_OPTION_NAME, MAX_TOPIC_NAME, NOTE_LEN};
+use arrayref::array_ref;
+use solana_program::msg;
 use solana_program::pubkey::Pubkey;
 use solana_program::{
     instruction::AccountMeta, instruction::Instruction, program_error::ProgramError,
 };
-use std::mem::size_of;
+use std::convert::TryFrom;
 use std::str::from_utf8;
 
+#[derive(Debug, PartialEq)]
 pub enum TopicInstruction<'a> {
     CreateTopic {
         topic_name: &'a str,
         option_name: &'a str,
+        category: [u8; 32],
+        config: TopicConfig,
     },
     AddOption {
         option_name: &'a str,
     },
     VoteTopic {
-        opt_idx: u8,
+        opt_idx: OptIdx,
+        /// Client-chosen value the voter may not reuse on this topic.
+        nonce: Option<u64>,
     },
     FinishTopic,
+    LogTally,
+    ResetTopic,
+    ReopenTopic,
+    BanVoter {
+        voter: Pubkey,
+    },
+    CloseTopic,
+    SetPaused {
+        paused: bool,
+    },
+    TokenVote {
+        opt_idx: OptIdx,
+    },
+    AddVoter {
+        voter: Pubkey,
+        weight: u64,
+    },
+    /// Signed vote on a `TallyMode::Quadratic` topic, committing `amount` lamports.
+    QuadraticVote {
+        opt_idx: OptIdx,
+        amount: u64,
+    },
+    AnonVote {
+        nullifier: [u8; 32],
+        opt_idx: OptIdx,
+    },
+    ReorderOptions {
+        order: [u8; MAX_OPTIONS],
+    },
+    ForceFinish,
+    RenameOption {
+        opt_idx: OptIdx,
+        name: &'a str,
+    },
+    AppendNote {
+        note: [u8; NOTE_LEN],
+    },
+    /// Same as `CreateTopic`, but a no-op when the topic already exists with this name and owner.
+    CreateTopicIfMissing {
+        topic_name: &'a str,
+        option_name: &'a str,
+        category: [u8; 32],
+        config: TopicConfig,
+    },
+    /// Permissionless repair of the topic's cached aggregates.
+    RecomputeCache,
+    /// Logs which option the signer voted for, changing nothing.
+    GetMyVote,
+    SetQuorum {
+        min_quorum: u32,
+    },
+    /// Signed vote on a `TallyMode::TokenWeight` topic, weighted by every token account given.
+    VoteMultiSource {
+        opt_idx: OptIdx,
+    },
+    /// Takes back the signer's vote, if the topic allows vote changes.
+    RetractVote,
+    /// Moves the signer's vote to `opt_idx`, if the topic allows vote changes.
+    ChangeVote {
+        opt_idx: OptIdx,
+    },
+    /// Zeroes a topic account whatever its contents, signed by the program's admin.
+    AdminReset,
+    /// Marks a finished topic's result as certified.
+    CertifyResult,
+}
+
+type CreateArgs<'a> = (&'a str, &'a str, [u8; 32], TopicConfig);
+
+/// Rejects a name before it is packed if it is empty or would not fit a `field_len` byte
+/// name field together with its `|` terminator.
+fn check_name_len(name: &str, field_len: usize) -> Result<(), ProgramError> {
+    if name.is_empty() || name.len() >= field_len {
+        return Err(ProgramError::InvalidArgument);
+    }
+    Ok(())
+}
+
+/// Splits the `category | config | topic_name_len: u8 | topic_name | option_name` payload of the
+/// create instructions. The length prefix keeps names containing `|` from shifting the split.
+fn unpack_create_args(rest: &[u8]) -> Result<CreateArgs<'_>, ProgramError> {
+    use ProgramError::InvalidInstructionData;
+    if rest.len() < 32 + TopicConfig::get_packed_len() {
+        return Err(InvalidInstructionData);
+    }
+    let (category, rest) = rest.split_at(32);
+    let category = *array_ref![category, 0, 32];
+    let (config, rest) = rest.split_at(TopicConfig::get_packed_len());
+    let config = TopicConfig::unpack_from_slice(config)?;
+    let (&topic_name_len, rest) = rest.split_first().ok_or(InvalidInstructionData)?;
+    if rest.len() < topic_name_len as usize {
+        return Err(InvalidInstructionData);
+    }
+    let (topic_name, option_name) = rest.split_at(topic_name_len as usize);
+    if topic_name.is_empty() || option_name.is_empty() {
+        return Err(InvalidInstructionData);
+    }
+    let topic_name = from_utf8(topic_name).map_err(|_| InvalidInstructionData)?;
+    let option_name = from_utf8(option_name).map_err(|_| InvalidInstructionData)?;
+    Ok((topic_name, option_name, category, config))
+}
+
+fn pack_create_args(buf: &mut Vec<u8>, args: CreateArgs) -> Result<(), ProgramError> {
+    let (topic_name, option_name, category, config) = args;
+    let topic_name_len =
+        u8::try_from(topic_name.len()).map_err(|_| ProgramError::InvalidArgument)?;
+    buf.extend_from_slice(&category);
+    let mut config_bytes = vec![0; TopicConfig::get_packed_len()];
+    config.pack_into_slice(&mut config_bytes);
+    buf.extend_from_slice(&config_bytes);
+    buf.push(topic_name_len);
+    buf.extend_from_slice(topic_name.as_bytes());
+    buf.extend_from_slice(option_name.as_bytes());
+    Ok(())
 }
 
 impl<'a> TopicInstruction<'a> {
@@ -27,60 +149,518 @@ impl<'a> TopicInstruction<'a> {
 
         Ok(match tag {
             0 => {
-                let mut split_index: usize = 0;
-                for (i, char) in rest.iter().enumerate() {
-                    if *char == '|' as u8 {
-                        split_index = i;
-                        break;
-                    }
-                }
-                let (topic_name, option_name) = rest.split_at(split_index);
-                let (_, option_name) = option_name.split_first().ok_or(InvalidInstructionData)?;
-                let topic_name = from_utf8(topic_name).unwrap();
-                let option_name = from_utf8(option_name).unwrap();
+                let (topic_name, option_name, category, config) = unpack_create_args(rest)?;
                 Self::CreateTopic {
                     topic_name,
                     option_name,
+                    category,
+                    config,
                 }
             }
             1 => {
-                let option_name = from_utf8(rest).unwrap();
+                if rest.is_empty() {
+                    return Err(InvalidInstructionData);
+                }
+                let option_name = from_utf8(rest).map_err(|_| InvalidInstructionData)?;
                 Self::AddOption { option_name }
             }
             2 => {
-                let opt_idx = rest[0];
-                Self::VoteTopic { opt_idx }
+                let (&opt_idx, nonce) = rest.split_first().ok_or(InvalidInstructionData)?;
+                let nonce = match nonce.len() {
+                    0 => None,
+                    8 => Some(u64::from_le_bytes(*array_ref![nonce, 0, 8])),
+                    _ => return Err(InvalidInstructionData),
+                };
+                Self::VoteTopic {
+                    opt_idx: OptIdx(opt_idx),
+                    nonce,
+                }
             }
             3 => Self::FinishTopic,
-            _ => return Err(InvalidInstructionData),
+            4 => Self::LogTally,
+            5 => Self::ResetTopic,
+            6 => Self::ReopenTopic,
+            7 => {
+                if rest.len() < 32 {
+                    return Err(InvalidInstructionData);
+                }
+                let voter = Pubkey::new_from_array(*array_ref![rest, 0, 32]);
+                Self::BanVoter { voter }
+            }
+            8 => Self::CloseTopic,
+            9 => {
+                let paused = *rest.first().ok_or(InvalidInstructionData)?;
+                Self::SetPaused {
+                    paused: paused == 1,
+                }
+            }
+            10 => {
+                let opt_idx = OptIdx(*rest.first().ok_or(InvalidInstructionData)?);
+                Self::TokenVote { opt_idx }
+            }
+            11 => {
+                if rest.len() < 40 {
+                    return Err(InvalidInstructionData);
+                }
+                let voter = Pubkey::new_from_array(*array_ref![rest, 0, 32]);
+                let weight = u64::from_le_bytes(*array_ref![rest, 32, 8]);
+                Self::AddVoter { voter, weight }
+            }
+            12 => {
+                if rest.len() < 9 {
+                    return Err(InvalidInstructionData);
+                }
+                let opt_idx = OptIdx(rest[0]);
+                let amount = u64::from_le_bytes(*array_ref![rest, 1, 8]);
+                Self::QuadraticVote { opt_idx, amount }
+            }
+            27 => {
+                if rest.len() < 33 {
+                    return Err(InvalidInstructionData);
+                }
+                let nullifier = *array_ref![rest, 0, 32];
+                let opt_idx = OptIdx(rest[32]);
+                Self::AnonVote { nullifier, opt_idx }
+            }
+            28 => {
+                if rest.len() < MAX_OPTIONS {
+                    return Err(InvalidInstructionData);
+                }
+                let order = *array_ref![rest, 0, MAX_OPTIONS];
+                Self::ReorderOptions { order }
+            }
+            29 => Self::ForceFinish,
+            30 => {
+                let (&opt_idx, name) = rest.split_first().ok_or(InvalidInstructionData)?;
+                if name.is_empty() {
+                    return Err(InvalidInstructionData);
+                }
+                let name = from_utf8(name).map_err(|_| InvalidInstructionData)?;
+                Self::RenameOption {
+                    opt_idx: OptIdx(opt_idx),
+                    name,
+                }
+            }
+            31 => {
+                if rest.len() < NOTE_LEN {
+                    return Err(InvalidInstructionData);
+                }
+                let note = *array_ref![rest, 0, NOTE_LEN];
+                Self::AppendNote { note }
+            }
+            32 => {
+                let (topic_name, option_name, category, config) = unpack_create_args(rest)?;
+                Self::CreateTopicIfMissing {
+                    topic_name,
+                    option_name,
+                    category,
+                    config,
+                }
+            }
+            33 => Self::RecomputeCache,
+            34 => Self::GetMyVote,
+            35 => {
+                if rest.len() < 4 {
+                    return Err(InvalidInstructionData);
+                }
+                let min_quorum = u32::from_le_bytes(*array_ref![rest, 0, 4]);
+                Self::SetQuorum { min_quorum }
+            }
+            36 => {
+                let opt_idx = OptIdx(*rest.first().ok_or(InvalidInstructionData)?);
+                Self::VoteMultiSource { opt_idx }
+            }
+            37 => Self::RetractVote,
+            38 => {
+                let opt_idx = OptIdx(*rest.first().ok_or(InvalidInstructionData)?);
+                Self::ChangeVote { opt_idx }
+            }
+            39 => Self::AdminReset,
+            40 => Self::CertifyResult,
+            _ => {
+                msg!("unknown instruction tag {}", tag);
+                return Err(TopicError::UnknownInstruction.into());
+            }
         })
     }
 
-    pub fn pack(&self) -> Vec<u8> {
-        let mut buf: Vec<u8> = Vec::with_capacity(size_of::<Self>());
+    /// Length in bytes of `pack()`'s output, for sizing buffers ahead of packing.
+    pub fn size_hint(&self) -> usize {
+        let create_len = |topic_name: &str, option_name: &str| {
+            32 + TopicConfig::get_packed_len() + 1 + topic_name.len() + option_name.len()
+        };
+        1 + match *self {
+            Self::CreateTopic {
+                topic_name,
+                option_name,
+                ..
+            }
+            | Self::CreateTopicIfMissing {
+                topic_name,
+                option_name,
+                ..
+            } => create_len(topic_name, option_name),
+            Self::AddOption { option_name } => option_name.len(),
+            Self::VoteTopic { nonce, .. } => 1 + nonce.map_or(0, |_| 8),
+            Self::FinishTopic
+            | Self::LogTally
+            | Self::ResetTopic
+            | Self::ReopenTopic
+            | Self::CloseTopic
+            | Self::ForceFinish
+            | Self::RecomputeCache
+            | Self::GetMyVote
+            | Self::RetractVote
+            | Self::AdminReset
+            | Self::CertifyResult => 0,
+            Self::BanVoter { .. } => 32,
+            Self::SetPaused { .. }
+            | Self::TokenVote { .. }
+            | Self::VoteMultiSource { .. }
+            | Self::ChangeVote { .. } => 1,
+            Self::AddVoter { .. } => 32 + 8,
+            Self::QuadraticVote { .. } => 1 + 8,
+            Self::AnonVote { .. } => 32 + 1,
+            Self::ReorderOptions { .. } => MAX_OPTIONS,
+            Self::RenameOption { name, .. } => 1 + name.len(),
+            Self::AppendNote { .. } => NOTE_LEN,
+            Self::SetQuorum { .. } => 4,
+        }
+    }
+
+    /// Packs the instruction data, failing with `InvalidArgument` if a topic name is too long
+    /// for its one-byte length prefix.
+    pub fn pack(&self) -> Result<Vec<u8>, ProgramError> {
+        let mut buf: Vec<u8> = Vec::with_capacity(self.size_hint());
         match *self {
             Self::CreateTopic {
                 topic_name,
                 option_name,
+                category,
+                config,
             } => {
                 buf.push(0);
-                buf.extend_from_slice(topic_name.as_bytes());
-                buf.push('|' as u8);
-                buf.extend_from_slice(option_name.as_bytes());
+                pack_create_args(&mut buf, (topic_name, option_name, category, config))?;
             }
             Self::AddOption { option_name } => {
                 buf.push(1);
                 buf.extend_from_slice(option_name.as_bytes());
             }
-            Self::VoteTopic { opt_idx } => {
+            Self::VoteTopic { opt_idx, nonce } => {
                 buf.push(2);
-                buf.push(opt_idx);
+                buf.push(opt_idx.into());
+                if let Some(nonce) = nonce {
+                    buf.extend_from_slice(&nonce.to_le_bytes());
+                }
             }
             Self::FinishTopic => {
                 buf.push(3);
             }
+            Self::LogTally => {
+                buf.push(4);
+            }
+            Self::ResetTopic => {
+                buf.push(5);
+            }
+            Self::ReopenTopic => {
+                buf.push(6);
+            }
+            Self::BanVoter { voter } => {
+                buf.push(7);
+                buf.extend_from_slice(voter.as_ref());
+            }
+            Self::CloseTopic => {
+                buf.push(8);
+            }
+            Self::SetPaused { paused } => {
+                buf.push(9);
+                buf.push(paused as u8);
+            }
+            Self::TokenVote { opt_idx } => {
+                buf.push(10);
+                buf.push(opt_idx.into());
+            }
+            Self::AddVoter { voter, weight } => {
+                buf.push(11);
+                buf.extend_from_slice(voter.as_ref());
+                buf.extend_from_slice(&weight.to_le_bytes());
+            }
+            Self::QuadraticVote { opt_idx, amount } => {
+                buf.push(12);
+                buf.push(opt_idx.into());
+                buf.extend_from_slice(&amount.to_le_bytes());
+            }
+            Self::AnonVote { nullifier, opt_idx } => {
+                buf.push(27);
+                buf.extend_from_slice(&nullifier);
+                buf.push(opt_idx.into());
+            }
+            Self::ReorderOptions { order } => {
+                buf.push(28);
+                buf.extend_from_slice(&order);
+            }
+            Self::ForceFinish => {
+                buf.push(29);
+            }
+            Self::RenameOption { opt_idx, name } => {
+                buf.push(30);
+                buf.push(opt_idx.into());
+                buf.extend_from_slice(name.as_bytes());
+            }
+            Self::AppendNote { note } => {
+                buf.push(31);
+                buf.extend_from_slice(&note);
+            }
+            Self::CreateTopicIfMissing {
+                topic_name,
+                option_name,
+                category,
+                config,
+            } => {
+                buf.push(32);
+                pack_create_args(&mut buf, (topic_name, option_name, category, config))?;
+            }
+            Self::RecomputeCache => {
+                buf.push(33);
+            }
+            Self::GetMyVote => {
+                buf.push(34);
+            }
+            Self::SetQuorum { min_quorum } => {
+                buf.push(35);
+                buf.extend_from_slice(&min_quorum.to_le_bytes());
+            }
+            Self::VoteMultiSource { opt_idx } => {
+                buf.push(36);
+                buf.push(opt_idx.into());
+            }
+            Self::RetractVote => {
+                buf.push(37);
+            }
+            Self::ChangeVote { opt_idx } => {
+                buf.push(38);
+                buf.push(opt_idx.into());
+            }
+            Self::AdminReset => {
+                buf.push(39);
+            }
+            Self::CertifyResult => {
+                buf.push(40);
+            }
         }
-        buf
+        Ok(buf)
+    }
+}
+
+/// Accounts for `CreateTopic` and `CreateTopicIfMissing`: the topic account, then its owner as signer.
+pub struct CreateTopicAccounts {
+    pub topic: Pubkey,
+    pub owner: Pubkey,
+}
+
+impl CreateTopicAccounts {
+    pub fn to_metas(&self) -> Vec<AccountMeta> {
+        vec![
+            AccountMeta::new(self.topic, false),
+            AccountMeta::new(self.owner, true),
+        ]
+    }
+}
+
+/// Accounts for `AddOption`: the topic account, then the adder as signer.
+pub struct AddOptionAccounts {
+    pub topic: Pubkey,
+    pub option_adder: Pubkey,
+}
+
+impl AddOptionAccounts {
+    pub fn to_metas(&self) -> Vec<AccountMeta> {
+        vec![
+            AccountMeta::new(self.topic, false),
+            AccountMeta::new(self.option_adder, true),
+        ]
+    }
+}
+
+/// Accounts for `VoteTopic`, `QuadraticVote`, `RetractVote` and `ChangeVote`: the topic account,
+/// then the voter as signer.
+pub struct VoteTopicAccounts {
+    pub topic: Pubkey,
+    pub voter: Pubkey,
+}
+
+impl VoteTopicAccounts {
+    pub fn to_metas(&self) -> Vec<AccountMeta> {
+        vec![
+            AccountMeta::new(self.topic, false),
+            AccountMeta::new(self.voter, true),
+        ]
+    }
+}
+
+/// Accounts for a `VoteTopic` on a topic requiring a co-signer: the topic account, the voter
+/// as signer, then the co-signer as signer.
+pub struct CosignedVoteAccounts {
+    pub topic: Pubkey,
+    pub voter: Pubkey,
+    pub cosigner: Pubkey,
+}
+
+impl CosignedVoteAccounts {
+    pub fn to_metas(&self) -> Vec<AccountMeta> {
+        vec![
+            AccountMeta::new(self.topic, false),
+            AccountMeta::new(self.voter, true),
+            AccountMeta::new_readonly(self.cosigner, true),
+        ]
+    }
+}
+
+/// Accounts for `VoteTopic` on an oracle-weighted topic: the topic account, the voter as
+/// signer, then the oracle account holding the voter's weight, read-only.
+pub struct OracleVoteAccounts {
+    pub topic: Pubkey,
+    pub voter: Pubkey,
+    pub oracle: Pubkey,
+}
+
+impl OracleVoteAccounts {
+    pub fn to_metas(&self) -> Vec<AccountMeta> {
+        vec![
+            AccountMeta::new(self.topic, false),
+            AccountMeta::new(self.voter, true),
+            AccountMeta::new_readonly(self.oracle, false),
+        ]
+    }
+}
+
+/// Accounts for `TokenVote`: the topic account, the voter as signer, then the voter's token
+/// account of the topic's `token_mint` backing the vote, read-only.
+pub struct TokenVoteAccounts {
+    pub topic: Pubkey,
+    pub voter: Pubkey,
+    pub token_account: Pubkey,
+}
+
+impl TokenVoteAccounts {
+    pub fn to_metas(&self) -> Vec<AccountMeta> {
+        vec![
+            AccountMeta::new(self.topic, false),
+            AccountMeta::new(self.voter, true),
+            AccountMeta::new_readonly(self.token_account, false),
+        ]
+    }
+}
+
+/// Accounts for `VoteMultiSource`: the topic account, the voter as signer, then each token
+/// account backing the vote.
+pub struct VoteMultiSourceAccounts {
+    pub topic: Pubkey,
+    pub voter: Pubkey,
+    pub token_accounts: Vec<Pubkey>,
+}
+
+impl VoteMultiSourceAccounts {
+    pub fn to_metas(&self) -> Vec<AccountMeta> {
+        let mut metas = vec![
+            AccountMeta::new(self.topic, false),
+            AccountMeta::new(self.voter, true),
+        ];
+        metas.extend(
+            self.token_accounts
+                .iter()
+                .map(|token_account| AccountMeta::new_readonly(*token_account, false)),
+        );
+        metas
+    }
+}
+
+/// Accounts for `AdminReset`: the topic account, then the program admin as signer.
+pub struct AdminResetAccounts {
+    pub topic: Pubkey,
+    pub admin: Pubkey,
+}
+
+impl AdminResetAccounts {
+    pub fn to_metas(&self) -> Vec<AccountMeta> {
+        vec![
+            AccountMeta::new(self.topic, false),
+            AccountMeta::new_readonly(self.admin, true),
+        ]
+    }
+}
+
+/// Accounts for `AnonVote` and `RecomputeCache`: only the topic account, nobody signs.
+pub struct AnonVoteAccounts {
+    pub topic: Pubkey,
+}
+
+impl AnonVoteAccounts {
+    pub fn to_metas(&self) -> Vec<AccountMeta> {
+        vec![AccountMeta::new(self.topic, false)]
+    }
+}
+
+/// Accounts for `LogTally`: only the topic account, read-only.
+pub struct LogTallyAccounts {
+    pub topic: Pubkey,
+}
+
+impl LogTallyAccounts {
+    pub fn to_metas(&self) -> Vec<AccountMeta> {
+        vec![AccountMeta::new_readonly(self.topic, false)]
+    }
+}
+
+/// Accounts for `GetMyVote`: the topic account, then the voter as signer, both read-only.
+pub struct GetMyVoteAccounts {
+    pub topic: Pubkey,
+    pub voter: Pubkey,
+}
+
+impl GetMyVoteAccounts {
+    pub fn to_metas(&self) -> Vec<AccountMeta> {
+        vec![
+            AccountMeta::new_readonly(self.topic, false),
+            AccountMeta::new_readonly(self.voter, true),
+        ]
+    }
+}
+
+/// Accounts for the owner-only instructions (`FinishTopic`, `ResetTopic`, `ReopenTopic`,
+/// `ReorderOptions`, `ForceFinish`, `RenameOption`, `AppendNote`, `BanVoter`, `SetPaused`,
+/// `AddVoter`, `SetQuorum`, `CertifyResult`): the topic account, then its owner as signer.
+pub struct TopicOwnerAccounts {
+    pub topic: Pubkey,
+    pub owner: Pubkey,
+}
+
+impl TopicOwnerAccounts {
+    pub fn to_metas(&self) -> Vec<AccountMeta> {
+        vec![
+            AccountMeta::new(self.topic, false),
+            AccountMeta::new(self.owner, true),
+        ]
+    }
+}
+
+/// Accounts for `CloseTopic`: the topic account, its owner as signer, the account refunded
+/// the remaining lamports, then the topic's fee destination.
+pub struct CloseTopicAccounts {
+    pub topic: Pubkey,
+    pub owner: Pubkey,
+    pub refund: Pubkey,
+    pub fee_destination: Pubkey,
+}
+
+impl CloseTopicAccounts {
+    pub fn to_metas(&self) -> Vec<AccountMeta> {
+        vec![
+            AccountMeta::new(self.topic, false),
+            AccountMeta::new(self.owner, true),
+            AccountMeta::new(self.refund, false),
+            AccountMeta::new(self.fee_destination, false),
+        ]
     }
 }
 
@@ -90,16 +670,84 @@ pub fn create_topic(
     topic_owner: &Pubkey,
     topic_name: &str,
     option_name: &str,
+    category: [u8; 32],
+    config: TopicConfig,
 ) -> Result<Instruction, ProgramError> {
+    check_name_len(topic_name, MAX_TOPIC_NAME)?;
+    check_name_len(option_name, MAX_OPTION_NAME)?;
     let data = TopicInstruction::CreateTopic {
         topic_name,
         option_name,
+        category,
+        config,
+    }
+    .pack()?;
+    let accounts = CreateTopicAccounts {
+        topic: *topic,
+        owner: *topic_owner,
+    }
+    .to_metas();
+
+    Ok(Instruction {
+        program_id: *program_id,
+        accounts,
+        data,
+    })
+}
+
+/// Creates a topic and casts the owner's vote in one transaction. The new topic has a
+/// single option, so `opt_idx` must be 0.
+#[allow(clippy::too_many_arguments)]
+pub fn create_and_vote(
+    program_id: &Pubkey,
+    topic: &Pubkey,
+    topic_owner: &Pubkey,
+    topic_name: &str,
+    option_name: &str,
+    category: [u8; 32],
+    config: TopicConfig,
+    opt_idx: OptIdx,
+) -> Result<Vec<Instruction>, ProgramError> {
+    if opt_idx.0 != 0 {
+        return Err(ProgramError::InvalidArgument);
+    }
+    Ok(vec![
+        create_topic(
+            program_id,
+            topic,
+            topic_owner,
+            topic_name,
+            option_name,
+            category,
+            config,
+        )?,
+        vote_topic(program_id, topic, topic_owner, opt_idx)?,
+    ])
+}
+
+pub fn create_topic_if_missing(
+    program_id: &Pubkey,
+    topic: &Pubkey,
+    topic_owner: &Pubkey,
+    topic_name: &str,
+    option_name: &str,
+    category: [u8; 32],
+    config: TopicConfig,
+) -> Result<Instruction, ProgramError> {
+    check_name_len(topic_name, MAX_TOPIC_NAME)?;
+    check_name_len(option_name, MAX_OPTION_NAME)?;
+    let data = TopicInstruction::CreateTopicIfMissing {
+        topic_name,
+        option_name,
+        category,
+        config,
     }
-    .pack();
-    let accounts = vec![
-        AccountMeta::new(*topic, false),
-        AccountMeta::new(*topic_owner, true),
-    ];
+    .pack()?;
+    let accounts = CreateTopicAccounts {
+        topic: *topic,
+        owner: *topic_owner,
+    }
+    .to_metas();
 
     Ok(Instruction {
         program_id: *program_id,
@@ -114,11 +762,13 @@ pub fn add_option(
     option_adder: &Pubkey,
     option_name: &str,
 ) -> Result<Instruction, ProgramError> {
-    let data = TopicInstruction::AddOption { option_name }.pack();
-    let accounts = vec![
-        AccountMeta::new(*topic, false),
-        AccountMeta::new(*option_adder, true),
-    ];
+    check_name_len(option_name, MAX_OPTION_NAME)?;
+    let data = TopicInstruction::AddOption { option_name }.pack()?;
+    let accounts = AddOptionAccounts {
+        topic: *topic,
+        option_adder: *option_adder,
+    }
+    .to_metas();
 
     Ok(Instruction {
         program_id: *program_id,
@@ -127,17 +777,40 @@ pub fn add_option(
     })
 }
 
+/// Canonical message a voter signs with their ed25519 key to authorize a vote off-chain.
+///
+/// Layout (33 bytes): `topic: [u8; 32] | opt_idx: u8`. A signature over it binds the vote to
+/// one topic and option, but not to a nonce, so verifiers must track replays themselves.
+/// Verification happens off-chain; no instruction checks these signatures.
+pub fn signed_vote_message(topic: &Pubkey, opt_idx: OptIdx) -> [u8; 33] {
+    let mut message = [0; 33];
+    message[..32].copy_from_slice(topic.as_ref());
+    message[32] = opt_idx.into();
+    message
+}
+
 pub fn vote_topic(
     program_id: &Pubkey,
     topic: &Pubkey,
     voter: &Pubkey,
-    opt_idx: u8,
+    opt_idx: OptIdx,
+) -> Result<Instruction, ProgramError> {
+    vote_topic_with_nonce(program_id, topic, voter, opt_idx, None)
+}
+
+pub fn vote_topic_with_nonce(
+    program_id: &Pubkey,
+    topic: &Pubkey,
+    voter: &Pubkey,
+    opt_idx: OptIdx,
+    nonce: Option<u64>,
 ) -> Result<Instruction, ProgramError> {
-    let data = TopicInstruction::VoteTopic { opt_idx }.pack();
-    let accounts = vec![
-        AccountMeta::new(*topic, false),
-        AccountMeta::new(*voter, true),
-    ];
+    let data = TopicInstruction::VoteTopic { opt_idx, nonce }.pack()?;
+    let accounts = VoteTopicAccounts {
+        topic: *topic,
+        voter: *voter,
+    }
+    .to_metas();
 
     Ok(Instruction {
         program_id: *program_id,
@@ -146,16 +819,118 @@ pub fn vote_topic(
     })
 }
 
-pub fn finish_topic(
+pub fn oracle_vote_topic(
     program_id: &Pubkey,
     topic: &Pubkey,
-    topic_owner: &Pubkey,
+    voter: &Pubkey,
+    oracle: &Pubkey,
+    opt_idx: OptIdx,
+) -> Result<Instruction, ProgramError> {
+    let data = TopicInstruction::VoteTopic {
+        opt_idx,
+        nonce: None,
+    }
+    .pack()?;
+    let accounts = OracleVoteAccounts {
+        topic: *topic,
+        voter: *voter,
+        oracle: *oracle,
+    }
+    .to_metas();
+
+    Ok(Instruction {
+        program_id: *program_id,
+        accounts,
+        data,
+    })
+}
+
+pub fn cosigned_vote_topic(
+    program_id: &Pubkey,
+    topic: &Pubkey,
+    voter: &Pubkey,
+    cosigner: &Pubkey,
+    opt_idx: OptIdx,
+) -> Result<Instruction, ProgramError> {
+    let data = TopicInstruction::VoteTopic {
+        opt_idx,
+        nonce: None,
+    }
+    .pack()?;
+    let accounts = CosignedVoteAccounts {
+        topic: *topic,
+        voter: *voter,
+        cosigner: *cosigner,
+    }
+    .to_metas();
+
+    Ok(Instruction {
+        program_id: *program_id,
+        accounts,
+        data,
+    })
+}
+
+pub fn token_vote(
+    program_id: &Pubkey,
+    topic: &Pubkey,
+    voter: &Pubkey,
+    token_account: &Pubkey,
+    opt_idx: OptIdx,
+) -> Result<Instruction, ProgramError> {
+    let data = TopicInstruction::TokenVote { opt_idx }.pack()?;
+    let accounts = TokenVoteAccounts {
+        topic: *topic,
+        voter: *voter,
+        token_account: *token_account,
+    }
+    .to_metas();
+
+    Ok(Instruction {
+        program_id: *program_id,
+        accounts,
+        data,
+    })
+}
+
+pub fn vote_multi_source(
+    program_id: &Pubkey,
+    topic: &Pubkey,
+    voter: &Pubkey,
+    token_accounts: &[Pubkey],
+    opt_idx: OptIdx,
+) -> Result<Instruction, ProgramError> {
+    if token_accounts.is_empty() {
+        return Err(ProgramError::NotEnoughAccountKeys);
+    }
+    let data = TopicInstruction::VoteMultiSource { opt_idx }.pack()?;
+    let accounts = VoteMultiSourceAccounts {
+        topic: *topic,
+        voter: *voter,
+        token_accounts: token_accounts.to_vec(),
+    }
+    .to_metas();
+
+    Ok(Instruction {
+        program_id: *program_id,
+        accounts,
+        data,
+    })
+}
+
+pub fn quadratic_vote(
+    program_id: &Pubkey,
+    topic: &Pubkey,
+    voter: &Pubkey,
+    opt_idx: OptIdx,
+    amount: u64,
 ) -> Result<Instruction, ProgramError> {
-    let data = TopicInstruction::FinishTopic.pack();
-    let accounts = vec![
-        AccountMeta::new(*topic, false),
-        AccountMeta::new(*topic_owner, true),
-    ];
+    let data = TopicInstruction::QuadraticVote { opt_idx, amount }.pack()?;
+    let accounts = VoteTopicAccounts {
+        topic: *topic,
+        voter: *voter,
+    }
+    .to_metas();
 
     Ok(Instruction {
         program_id: *program_id,
@@ -163,3 +938,943 @@ pub fn finish_topic(
         data,
     })
 }
+
+pub fn finish_topic(
+    program_id: &Pubkey,
+    topic: &Pubkey,
+    topic_owner: &Pubkey,
+) -> Result<Instruction, ProgramError> {
+    let data = TopicInstruction::FinishTopic.pack()?;
+    let accounts = TopicOwnerAccounts {
+        topic: *topic,
+        owner: *topic_owner,
+    }
+    .to_metas();
+
+    Ok(Instruction {
+        program_id: *program_id,
+        accounts,
+        data,
+    })
+}
+
+pub fn anon_vote(
+    program_id: &Pubkey,
+    topic: &Pubkey,
+    nullifier: [u8; 32],
+    opt_idx: OptIdx,
+) -> Result<Instruction, ProgramError> {
+    let data = TopicInstruction::AnonVote { nullifier, opt_idx }.pack()?;
+    let accounts = AnonVoteAccounts { topic: *topic }.to_metas();
+
+    Ok(Instruction {
+        program_id: *program_id,
+        accounts,
+        data,
+    })
+}
+
+pub fn log_tally(program_id: &Pubkey, topic: &Pubkey) -> Result<Instruction, ProgramError> {
+    let data = TopicInstruction::LogTally.pack()?;
+    let accounts = LogTallyAccounts { topic: *topic }.to_metas();
+
+    Ok(Instruction {
+        program_id: *program_id,
+        accounts,
+        data,
+    })
+}
+
+pub fn recompute_cache(program_id: &Pubkey, topic: &Pubkey) -> Result<Instruction, ProgramError> {
+    let data = TopicInstruction::RecomputeCache.pack()?;
+    let accounts = AnonVoteAccounts { topic: *topic }.to_metas();
+
+    Ok(Instruction {
+        program_id: *program_id,
+        accounts,
+        data,
+    })
+}
+
+pub fn retract_vote(
+    program_id: &Pubkey,
+    topic: &Pubkey,
+    voter: &Pubkey,
+) -> Result<Instruction, ProgramError> {
+    let data = TopicInstruction::RetractVote.pack()?;
+    let accounts = VoteTopicAccounts {
+        topic: *topic,
+        voter: *voter,
+    }
+    .to_metas();
+
+    Ok(Instruction {
+        program_id: *program_id,
+        accounts,
+        data,
+    })
+}
+
+pub fn change_vote(
+    program_id: &Pubkey,
+    topic: &Pubkey,
+    voter: &Pubkey,
+    opt_idx: OptIdx,
+) -> Result<Instruction, ProgramError> {
+    let data = TopicInstruction::ChangeVote { opt_idx }.pack()?;
+    let accounts = VoteTopicAccounts {
+        topic: *topic,
+        voter: *voter,
+    }
+    .to_metas();
+
+    Ok(Instruction {
+        program_id: *program_id,
+        accounts,
+        data,
+    })
+}
+
+pub fn admin_reset(
+    program_id: &Pubkey,
+    topic: &Pubkey,
+    admin: &Pubkey,
+) -> Result<Instruction, ProgramError> {
+    let data = TopicInstruction::AdminReset.pack()?;
+    let accounts = AdminResetAccounts {
+        topic: *topic,
+        admin: *admin,
+    }
+    .to_metas();
+
+    Ok(Instruction {
+        program_id: *program_id,
+        accounts,
+        data,
+    })
+}
+
+pub fn get_my_vote(
+    program_id: &Pubkey,
+    topic: &Pubkey,
+    voter: &Pubkey,
+) -> Result<Instruction, ProgramError> {
+    let data = TopicInstruction::GetMyVote.pack()?;
+    let accounts = GetMyVoteAccounts {
+        topic: *topic,
+        voter: *voter,
+    }
+    .to_metas();
+
+    Ok(Instruction {
+        program_id: *program_id,
+        accounts,
+        data,
+    })
+}
+
+pub fn reset_topic(
+    program_id: &Pubkey,
+    topic: &Pubkey,
+    topic_owner: &Pubkey,
+) -> Result<Instruction, ProgramError> {
+    let data = TopicInstruction::ResetTopic.pack()?;
+    let accounts = TopicOwnerAccounts {
+        topic: *topic,
+        owner: *topic_owner,
+    }
+    .to_metas();
+
+    Ok(Instruction {
+        program_id: *program_id,
+        accounts,
+        data,
+    })
+}
+
+pub fn reopen_topic(
+    program_id: &Pubkey,
+    topic: &Pubkey,
+    topic_owner: &Pubkey,
+) -> Result<Instruction, ProgramError> {
+    let data = TopicInstruction::ReopenTopic.pack()?;
+    let accounts = TopicOwnerAccounts {
+        topic: *topic,
+        owner: *topic_owner,
+    }
+    .to_metas();
+
+    Ok(Instruction {
+        program_id: *program_id,
+        accounts,
+        data,
+    })
+}
+
+pub fn set_paused(
+    program_id: &Pubkey,
+    topic: &Pubkey,
+    topic_owner: &Pubkey,
+    paused: bool,
+) -> Result<Instruction, ProgramError> {
+    let data = TopicInstruction::SetPaused { paused }.pack()?;
+    let accounts = TopicOwnerAccounts {
+        topic: *topic,
+        owner: *topic_owner,
+    }
+    .to_metas();
+
+    Ok(Instruction {
+        program_id: *program_id,
+        accounts,
+        data,
+    })
+}
+
+pub fn set_quorum(
+    program_id: &Pubkey,
+    topic: &Pubkey,
+    topic_owner: &Pubkey,
+    min_quorum: u32,
+) -> Result<Instruction, ProgramError> {
+    let data = TopicInstruction::SetQuorum { min_quorum }.pack()?;
+    let accounts = TopicOwnerAccounts {
+        topic: *topic,
+        owner: *topic_owner,
+    }
+    .to_metas();
+
+    Ok(Instruction {
+        program_id: *program_id,
+        accounts,
+        data,
+    })
+}
+
+pub fn add_voter(
+    program_id: &Pubkey,
+    topic: &Pubkey,
+    topic_owner: &Pubkey,
+    voter: &Pubkey,
+    weight: u64,
+) -> Result<Instruction, ProgramError> {
+    let data = TopicInstruction::AddVoter {
+        voter: *voter,
+        weight,
+    }
+    .pack()?;
+    let accounts = TopicOwnerAccounts {
+        topic: *topic,
+        owner: *topic_owner,
+    }
+    .to_metas();
+
+    Ok(Instruction {
+        program_id: *program_id,
+        accounts,
+        data,
+    })
+}
+
+pub fn ban_voter(
+    program_id: &Pubkey,
+    topic: &Pubkey,
+    topic_owner: &Pubkey,
+    voter: &Pubkey,
+) -> Result<Instruction, ProgramError> {
+    let data = TopicInstruction::BanVoter { voter: *voter }.pack()?;
+    let accounts = TopicOwnerAccounts {
+        topic: *topic,
+        owner: *topic_owner,
+    }
+    .to_metas();
+
+    Ok(Instruction {
+        program_id: *program_id,
+        accounts,
+        data,
+    })
+}
+
+pub fn close_topic(
+    program_id: &Pubkey,
+    topic: &Pubkey,
+    topic_owner: &Pubkey,
+    refund: &Pubkey,
+    fee_destination: &Pubkey,
+) -> Result<Instruction, ProgramError> {
+    let data = TopicInstruction::CloseTopic.pack()?;
+    let accounts = CloseTopicAccounts {
+        topic: *topic,
+        owner: *topic_owner,
+        refund: *refund,
+        fee_destination: *fee_destination,
+    }
+    .to_metas();
+
+    Ok(Instruction {
+        program_id: *program_id,
+        accounts,
+        data,
+    })
+}
+
+pub fn reorder_options(
+    program_id: &Pubkey,
+    topic: &Pubkey,
+    topic_owner: &Pubkey,
+    order: [u8; MAX_OPTIONS],
+) -> Result<Instruction, ProgramError> {
+    let data = TopicInstruction::ReorderOptions { order }.pack()?;
+    let accounts = TopicOwnerAccounts {
+        topic: *topic,
+        owner: *topic_owner,
+    }
+    .to_metas();
+
+    Ok(Instruction {
+        program_id: *program_id,
+        accounts,
+        data,
+    })
+}
+
+pub fn force_finish(
+    program_id: &Pubkey,
+    topic: &Pubkey,
+    topic_owner: &Pubkey,
+) -> Result<Instruction, ProgramError> {
+    let data = TopicInstruction::ForceFinish.pack()?;
+    let accounts = TopicOwnerAccounts {
+        topic: *topic,
+        owner: *topic_owner,
+    }
+    .to_metas();
+
+    Ok(Instruction {
+        program_id: *program_id,
+        accounts,
+        data,
+    })
+}
+
+pub fn certify_result(
+    program_id: &Pubkey,
+    topic: &Pubkey,
+    topic_owner: &Pubkey,
+) -> Result<Instruction, ProgramError> {
+    let data = TopicInstruction::CertifyResult.pack()?;
+    let accounts = TopicOwnerAccounts {
+        topic: *topic,
+        owner: *topic_owner,
+    }
+    .to_metas();
+
+    Ok(Instruction {
+        program_id: *program_id,
+        accounts,
+        data,
+    })
+}
+
+pub fn rename_option(
+    program_id: &Pubkey,
+    topic: &Pubkey,
+    topic_owner: &Pubkey,
+    opt_idx: OptIdx,
+    name: &str,
+) -> Result<Instruction, ProgramError> {
+    check_name_len(name, MAX_OPTION_NAME)?;
+    let data = TopicInstruction::RenameOption { opt_idx, name }.pack()?;
+    let accounts = TopicOwnerAccounts {
+        topic: *topic,
+        owner: *topic_owner,
+    }
+    .to_metas();
+
+    Ok(Instruction {
+        program_id: *program_id,
+        accounts,
+        data,
+    })
+}
+
+/// Builds an `AppendNote` instruction, zero-padding `note` to `NOTE_LEN` bytes.
+pub fn append_note(
+    program_id: &Pubkey,
+    topic: &Pubkey,
+    topic_owner: &Pubkey,
+    note: &str,
+) -> Result<Instruction, ProgramError> {
+    if note.len() > NOTE_LEN {
+        return Err(ProgramError::InvalidArgument);
+    }
+    let mut note_data = [0; NOTE_LEN];
+    note_data[..note.len()].copy_from_slice(note.as_bytes());
+    let data = TopicInstruction::AppendNote { note: note_data }.pack()?;
+    let accounts = TopicOwnerAccounts {
+        topic: *topic,
+        owner: *topic_owner,
+    }
+    .to_metas();
+
+    Ok(Instruction {
+        program_id: *program_id,
+        accounts,
+        data,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::instruction::*;
+    use crate::state::TallyMode;
+    use proptest::prelude::*;
+    use std::convert::TryFrom;
+
+    fn assert_metas(instruction: &Instruction, expect: &[(Pubkey, bool, bool)]) {
+        let metas: Vec<(Pubkey, bool, bool)> = instruction
+            .accounts
+            .iter()
+            .map(|meta| (meta.pubkey, meta.is_signer, meta.is_writable))
+            .collect();
+        assert_eq!(expect, &metas[..]);
+    }
+
+    #[test]
+    fn test_instruction_account_metas() {
+        let pid = Pubkey::new_unique();
+        let topic = Pubkey::new_unique();
+        let signer = Pubkey::new_unique();
+        let config = TopicConfig::default();
+
+        let i = create_topic(&pid, &topic, &signer, "topic", "option", [0; 32], config).unwrap();
+        assert_metas(&i, &[(topic, false, true), (signer, true, true)]);
+        let i = create_topic_if_missing(&pid, &topic, &signer, "topic", "option", [0; 32], config)
+            .unwrap();
+        assert_metas(&i, &[(topic, false, true), (signer, true, true)]);
+        let i = add_option(&pid, &topic, &signer, "option").unwrap();
+        assert_metas(&i, &[(topic, false, true), (signer, true, true)]);
+        let i = vote_topic(&pid, &topic, &signer, OptIdx(0)).unwrap();
+        assert_metas(&i, &[(topic, false, true), (signer, true, true)]);
+        let token_account = Pubkey::new_unique();
+        let token_account2 = Pubkey::new_unique();
+        let i = vote_multi_source(
+            &pid,
+            &topic,
+            &signer,
+            &[token_account, token_account2],
+            OptIdx(0),
+        )
+        .unwrap();
+        assert_metas(
+            &i,
+            &[
+                (topic, false, true),
+                (signer, true, true),
+                (token_account, false, false),
+                (token_account2, false, false),
+            ],
+        );
+        assert_eq!(
+            Err(ProgramError::NotEnoughAccountKeys),
+            vote_multi_source(&pid, &topic, &signer, &[], OptIdx(0))
+        );
+        let i = token_vote(&pid, &topic, &signer, &token_account, OptIdx(0)).unwrap();
+        assert_metas(
+            &i,
+            &[
+                (topic, false, true),
+                (signer, true, true),
+                (token_account, false, false),
+            ],
+        );
+        let cosigner = Pubkey::new_unique();
+        let i = admin_reset(&pid, &topic, &signer).unwrap();
+        assert_metas(&i, &[(topic, false, true), (signer, true, false)]);
+        let oracle = Pubkey::new_unique();
+        let i = oracle_vote_topic(&pid, &topic, &signer, &oracle, OptIdx(0)).unwrap();
+        assert_metas(
+            &i,
+            &[
+                (topic, false, true),
+                (signer, true, true),
+                (oracle, false, false),
+            ],
+        );
+        let i = cosigned_vote_topic(&pid, &topic, &signer, &cosigner, OptIdx(0)).unwrap();
+        assert_metas(
+            &i,
+            &[
+                (topic, false, true),
+                (signer, true, true),
+                (cosigner, true, false),
+            ],
+        );
+        let i = anon_vote(&pid, &topic, [0; 32], OptIdx(0)).unwrap();
+        assert_metas(&i, &[(topic, false, true)]);
+        let i = recompute_cache(&pid, &topic).unwrap();
+        assert_metas(&i, &[(topic, false, true)]);
+        let i = log_tally(&pid, &topic).unwrap();
+        assert_metas(&i, &[(topic, false, false)]);
+        let i = get_my_vote(&pid, &topic, &signer).unwrap();
+        assert_metas(&i, &[(topic, false, false), (signer, true, false)]);
+        let (refund, fee) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let i = close_topic(&pid, &topic, &signer, &refund, &fee).unwrap();
+        assert_metas(
+            &i,
+            &[
+                (topic, false, true),
+                (signer, true, true),
+                (refund, false, true),
+                (fee, false, true),
+            ],
+        );
+        for i in [
+            finish_topic(&pid, &topic, &signer).unwrap(),
+            force_finish(&pid, &topic, &signer).unwrap(),
+            certify_result(&pid, &topic, &signer).unwrap(),
+            reset_topic(&pid, &topic, &signer).unwrap(),
+            reopen_topic(&pid, &topic, &signer).unwrap(),
+            reorder_options(&pid, &topic, &signer, [0; MAX_OPTIONS]).unwrap(),
+            rename_option(&pid, &topic, &signer, OptIdx(0), "option").unwrap(),
+            append_note(&pid, &topic, &signer, "note").unwrap(),
+            ban_voter(&pid, &topic, &signer, &signer).unwrap(),
+            set_paused(&pid, &topic, &signer, true).unwrap(),
+            set_quorum(&pid, &topic, &signer, 1).unwrap(),
+            add_voter(&pid, &topic, &signer, &signer, 1).unwrap(),
+        ] {
+            assert_metas(&i, &[(topic, false, true), (signer, true, true)]);
+        }
+    }
+
+    #[test]
+    fn test_unpack_add_option_empty_name() {
+        assert_eq!(
+            Some(ProgramError::InvalidInstructionData),
+            TopicInstruction::unpack(&[1]).err()
+        );
+    }
+
+    #[test]
+    fn test_unpack_add_option_invalid_utf8() {
+        assert_eq!(
+            Some(ProgramError::InvalidInstructionData),
+            TopicInstruction::unpack(&[1, 0xff]).err()
+        );
+    }
+
+    #[test]
+    fn test_unpack_create_topic_empty_names() {
+        let mut data = vec![0; 1 + 32 + TopicConfig::get_packed_len()];
+        for payload in [&b"\x00option"[..], b"\x05topic", b"\x00", b"", b"\x06topic"] {
+            let mut input = data.clone();
+            input.extend_from_slice(payload);
+            assert_eq!(
+                Err(ProgramError::InvalidInstructionData),
+                TopicInstruction::unpack(&input)
+            );
+        }
+
+        data.extend_from_slice(b"\x05topicoption");
+        assert!(matches!(
+            TopicInstruction::unpack(&data),
+            Ok(TopicInstruction::CreateTopic {
+                topic_name: "topic",
+                option_name: "option",
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn test_append_note_round_trip() {
+        let mut note = [0; NOTE_LEN];
+        note[..9].copy_from_slice(b"certified");
+        let data = TopicInstruction::AppendNote { note }.pack().unwrap();
+        assert_eq!(1 + NOTE_LEN, data.len());
+        assert_eq!(
+            Ok(TopicInstruction::AppendNote { note }),
+            TopicInstruction::unpack(&data)
+        );
+        assert_eq!(
+            Err(ProgramError::InvalidInstructionData),
+            TopicInstruction::unpack(&data[..NOTE_LEN])
+        );
+
+        let pid = Pubkey::new_unique();
+        let key = Pubkey::new_unique();
+        let i = append_note(&pid, &key, &key, "certified").unwrap();
+        assert_eq!(data, i.data);
+        assert_eq!(
+            Some(ProgramError::InvalidArgument),
+            append_note(&pid, &key, &key, &"x".repeat(NOTE_LEN + 1)).err()
+        );
+    }
+
+    #[test]
+    fn test_signed_vote_message() {
+        use solana_sdk::signature::{Keypair, Signer};
+
+        let topic = Pubkey::new_unique();
+        let voter = Keypair::new();
+        let message = signed_vote_message(&topic, OptIdx(2));
+        assert_eq!(topic.as_ref(), &message[..32]);
+        assert_eq!(2, message[32]);
+
+        let signature = voter.sign_message(&message);
+        assert!(signature.verify(voter.pubkey().as_ref(), &message));
+
+        let tampered = signed_vote_message(&topic, OptIdx(3));
+        assert!(!signature.verify(voter.pubkey().as_ref(), &tampered));
+        let other_topic = signed_vote_message(&Pubkey::new_unique(), OptIdx(2));
+        assert!(!signature.verify(voter.pubkey().as_ref(), &other_topic));
+        let wrong_key = Keypair::new();
+        assert!(!signature.verify(wrong_key.pubkey().as_ref(), &message));
+        let mut flipped = message;
+        flipped[0] ^= 1;
+        assert!(!signature.verify(voter.pubkey().as_ref(), &flipped));
+    }
+
+    #[test]
+    fn test_vote_topic_nonce_round_trip() {
+        for nonce in [None, Some(0), Some(u64::MAX)] {
+            let data = TopicInstruction::VoteTopic {
+                opt_idx: OptIdx(3),
+                nonce,
+            }
+            .pack()
+            .unwrap();
+            assert_eq!(
+                Ok(TopicInstruction::VoteTopic {
+                    opt_idx: OptIdx(3),
+                    nonce
+                }),
+                TopicInstruction::unpack(&data)
+            );
+        }
+        assert_eq!(
+            Err(ProgramError::InvalidInstructionData),
+            TopicInstruction::unpack(&[2, 0, 1, 2, 3])
+        );
+    }
+
+    #[test]
+    fn test_create_and_vote() {
+        let pid = Pubkey::new_unique();
+        let topic = Pubkey::new_unique();
+        let owner = Pubkey::new_unique();
+        let config = TopicConfig::default();
+
+        let ixs = create_and_vote(
+            &pid,
+            &topic,
+            &owner,
+            "topic",
+            "option",
+            [0; 32],
+            config,
+            OptIdx(0),
+        )
+        .unwrap();
+        assert_eq!(
+            vec![
+                create_topic(&pid, &topic, &owner, "topic", "option", [0; 32], config).unwrap(),
+                vote_topic(&pid, &topic, &owner, OptIdx(0)).unwrap(),
+            ],
+            ixs
+        );
+        assert_eq!(
+            Err(ProgramError::InvalidArgument),
+            create_and_vote(
+                &pid,
+                &topic,
+                &owner,
+                "topic",
+                "option",
+                [0; 32],
+                config,
+                OptIdx(1)
+            )
+        );
+    }
+
+    #[test]
+    fn test_size_hint_matches_pack() {
+        let voter = Pubkey::new_unique();
+        let instructions = [
+            TopicInstruction::CreateTopic {
+                topic_name: "topic",
+                option_name: "option",
+                category: [1; 32],
+                config: TopicConfig::default(),
+            },
+            TopicInstruction::AddOption {
+                option_name: "option",
+            },
+            TopicInstruction::VoteTopic {
+                opt_idx: OptIdx(1),
+                nonce: None,
+            },
+            TopicInstruction::VoteTopic {
+                opt_idx: OptIdx(1),
+                nonce: Some(7),
+            },
+            TopicInstruction::FinishTopic,
+            TopicInstruction::LogTally,
+            TopicInstruction::ResetTopic,
+            TopicInstruction::ReopenTopic,
+            TopicInstruction::BanVoter { voter },
+            TopicInstruction::CloseTopic,
+            TopicInstruction::SetPaused { paused: true },
+            TopicInstruction::TokenVote { opt_idx: OptIdx(0) },
+            TopicInstruction::AddVoter { voter, weight: 3 },
+            TopicInstruction::QuadraticVote {
+                opt_idx: OptIdx(0),
+                amount: 9,
+            },
+            TopicInstruction::AnonVote {
+                nullifier: [2; 32],
+                opt_idx: OptIdx(0),
+            },
+            TopicInstruction::ReorderOptions {
+                order: [0; MAX_OPTIONS],
+            },
+            TopicInstruction::ForceFinish,
+            TopicInstruction::RenameOption {
+                opt_idx: OptIdx(0),
+                name: "renamed",
+            },
+            TopicInstruction::AppendNote {
+                note: [0; NOTE_LEN],
+            },
+            TopicInstruction::CreateTopicIfMissing {
+                topic_name: "topic",
+                option_name: "option",
+                category: [1; 32],
+                config: TopicConfig::default(),
+            },
+            TopicInstruction::RecomputeCache,
+            TopicInstruction::GetMyVote,
+            TopicInstruction::SetQuorum { min_quorum: 5 },
+            TopicInstruction::VoteMultiSource { opt_idx: OptIdx(2) },
+            TopicInstruction::RetractVote,
+            TopicInstruction::ChangeVote { opt_idx: OptIdx(1) },
+            TopicInstruction::AdminReset,
+            TopicInstruction::CertifyResult,
+        ];
+        for i in &instructions {
+            assert_eq!(i.pack().unwrap().len(), i.size_hint(), "{:?}", i);
+        }
+    }
+
+    prop_compose! {
+        fn arb_config()(
+            flags in any::<[bool; 9]>(),
+            min_voter_lamports in any::<u64>(),
+            min_quorum in 1..u32::MAX,
+            max_voters_per_option in 0..=30u8,
+            fee_bps in 0..=10_000u16,
+            fee_destination in any::<[u8; 32]>(),
+            max_options in 0..=MAX_OPTIONS as u8,
+            min_options in 0..=MAX_OPTIONS as u8,
+            salt in any::<[u8; 16]>(),
+            cosigner in any::<[u8; 32]>(),
+            deadline in any::<i64>(),
+            tally_mode in 0..4u8,
+            min_action_interval in 0..i64::MAX,
+            oracle_authority in any::<[u8; 32]>(),
+            token_mint in any::<[u8; 32]>(),
+        ) -> TopicConfig {
+            TopicConfig {
+                distinct_names: flags[0],
+                hide_results_until_finish: flags[1],
+                lock_options_on_first_vote: flags[2],
+                min_voter_lamports,
+                min_quorum,
+                weight_by_lamports: flags[3],
+                sealed_after_finish: flags[4],
+                max_voters_per_option,
+                fee_bps,
+                fee_destination: Pubkey::new_from_array(fee_destination),
+                max_options,
+                require_cosigner: flags[5],
+                cosigner: Pubkey::new_from_array(cosigner),
+                deadline,
+                tally_mode: TallyMode::try_from(tally_mode).unwrap(),
+                auto_finish_on_quorum: flags[6],
+                min_action_interval,
+                allow_vote_changes: flags[7],
+                oracle_weighted: flags[8],
+                oracle_authority: Pubkey::new_from_array(oracle_authority),
+                min_options,
+                salt,
+                token_mint: Pubkey::new_from_array(token_mint),
+            }
+        }
+    }
+
+    proptest! {
+        #[test]
+        fn test_pack_unpack_round_trip(
+            topic_name in "\\PC{1,20}",
+            option_name in "\\PC{1,20}",
+            category in any::<[u8; 32]>(),
+            config in arb_config(),
+            opt_idx in any::<u8>(),
+            nonce in any::<Option<u64>>(),
+            key in any::<[u8; 32]>(),
+            amount in any::<u64>(),
+            min_quorum in any::<u32>(),
+            flag in any::<bool>(),
+            order in any::<[u8; MAX_OPTIONS]>(),
+            note in prop::collection::vec(any::<u8>(), NOTE_LEN),
+        ) {
+            let opt_idx = OptIdx(opt_idx);
+            let key = Pubkey::new_from_array(key);
+            let note = *array_ref![note, 0, NOTE_LEN];
+            let instructions = [
+                TopicInstruction::CreateTopic {
+                    topic_name: &topic_name,
+                    option_name: &option_name,
+                    category,
+                    config,
+                },
+                TopicInstruction::AddOption {
+                    option_name: &option_name,
+                },
+                TopicInstruction::VoteTopic { opt_idx, nonce },
+                TopicInstruction::FinishTopic,
+                TopicInstruction::LogTally,
+                TopicInstruction::ResetTopic,
+                TopicInstruction::ReopenTopic,
+                TopicInstruction::BanVoter { voter: key },
+                TopicInstruction::CloseTopic,
+                TopicInstruction::SetPaused { paused: flag },
+                TopicInstruction::TokenVote { opt_idx },
+                TopicInstruction::AddVoter {
+                    voter: key,
+                    weight: amount,
+                },
+                TopicInstruction::QuadraticVote { opt_idx, amount },
+                TopicInstruction::AnonVote {
+                    nullifier: key.to_bytes(),
+                    opt_idx,
+                },
+                TopicInstruction::ReorderOptions { order },
+                TopicInstruction::ForceFinish,
+                TopicInstruction::RenameOption {
+                    opt_idx,
+                    name: &option_name,
+                },
+                TopicInstruction::AppendNote { note },
+                TopicInstruction::CreateTopicIfMissing {
+                    topic_name: &topic_name,
+                    option_name: &option_name,
+                    category,
+                    config,
+                },
+                TopicInstruction::RecomputeCache,
+                TopicInstruction::GetMyVote,
+                TopicInstruction::SetQuorum { min_quorum },
+                TopicInstruction::VoteMultiSource { opt_idx },
+                TopicInstruction::RetractVote,
+                TopicInstruction::ChangeVote { opt_idx },
+                TopicInstruction::AdminReset,
+                TopicInstruction::CertifyResult,
+            ];
+            for i in &instructions {
+                let data = i.pack().unwrap();
+                let unpacked = TopicInstruction::unpack(&data);
+                prop_assert_eq!(Ok(i), unpacked.as_ref());
+            }
+        }
+    }
+
+    #[test]
+    fn test_pack_rejects_topic_name_over_length_prefix() {
+        let topic_name = "a".repeat(256);
+        for i in [
+            TopicInstruction::CreateTopic {
+                topic_name: &topic_name,
+                option_name: "option",
+                category: [0; 32],
+                config: TopicConfig::default(),
+            },
+            TopicInstruction::CreateTopicIfMissing {
+                topic_name: &topic_name,
+                option_name: "option",
+                category: [0; 32],
+                config: TopicConfig::default(),
+            },
+        ] {
+            assert_eq!(Err(ProgramError::InvalidArgument), i.pack());
+        }
+    }
+
+    #[test]
+    fn test_builders_reject_long_names() {
+        let pid = Pubkey::new_unique();
+        let topic = Pubkey::new_unique();
+        let signer = Pubkey::new_unique();
+        let config = TopicConfig::default();
+        let longest = "a".repeat(MAX_OPTION_NAME - 1);
+        let too_long = "a".repeat(MAX_OPTION_NAME);
+
+        for (topic_name, option_name) in [
+            (too_long.as_str(), "option"),
+            ("topic", too_long.as_str()),
+            ("", "option"),
+        ] {
+            assert_eq!(
+                Err(ProgramError::InvalidArgument),
+                create_topic(
+                    &pid,
+                    &topic,
+                    &signer,
+                    topic_name,
+                    option_name,
+                    [0; 32],
+                    config
+                )
+            );
+            assert_eq!(
+                Err(ProgramError::InvalidArgument),
+                create_topic_if_missing(
+                    &pid,
+                    &topic,
+                    &signer,
+                    topic_name,
+                    option_name,
+                    [0; 32],
+                    config
+                )
+            );
+            assert_eq!(
+                Err(ProgramError::InvalidArgument),
+                create_and_vote(
+                    &pid,
+                    &topic,
+                    &signer,
+                    topic_name,
+                    option_name,
+                    [0; 32],
+                    config,
+                    OptIdx(0)
+                )
+            );
+        }
+        create_topic(&pid, &topic, &signer, &longest, &longest, [0; 32], config).unwrap();
+
+        assert_eq!(
+            Err(ProgramError::InvalidArgument),
+            add_option(&pid, &topic, &signer, &too_long)
+        );
+        add_option(&pid, &topic, &signer, &longest).unwrap();
+        assert_eq!(
+            Err(ProgramError::InvalidArgument),
+            rename_option(&pid, &topic, &signer, OptIdx(0), &too_long)
+        );
+        rename_option(&pid, &topic, &signer, OptIdx(0), &longest).unwrap();
+    }
+}
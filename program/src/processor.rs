@@ -2,38 +2,234 @@ use solana_program::{
     account_info::{next_account_info, AccountInfo},
     entrypoint::ProgramResult,
     msg,
+    native_token::LAMPORTS_PER_SOL,
+    program::set_return_data,
     program_error::ProgramError,
     pubkey::Pubkey,
+    sysvar::{clock::Clock, Sysvar},
 };
 
-use crate::{instruction::TopicInstruction, state::Topic};
+use crate::{
+    error::TopicError,
+    instruction::TopicInstruction,
+    state::{
+        OptIdx, OracleWeight, TallyMode, TokenAccountData, Topic, TopicConfig, TopicState,
+        MAX_OPTIONS, NOTE_LEN, TOKEN_PROGRAM_ID,
+    },
+    util::{decode_pubkey, pack_u32, pack_u64, str_unpack},
+};
+use arrayref::array_mut_ref;
+
+/// Key allowed to wipe topic accounts with `AdminReset`, read in base58 from the
+/// `XPROGRAM_ADMIN` environment variable when the program is built. A malformed key fails the
+/// build; without one, `AdminReset` is disabled.
+pub const ADMIN: Option<Pubkey> = match option_env!("XPROGRAM_ADMIN") {
+    Some(key) => Some(Pubkey::new_from_array(decode_pubkey(key))),
+    None => None,
+};
+
+/// Rejects instructions that were not given exactly the accounts they use.
+fn check_accounts_len(accounts: &[AccountInfo], expected: usize) -> ProgramResult {
+    if accounts.len() < expected {
+        return Err(ProgramError::NotEnoughAccountKeys);
+    }
+    if accounts.len() > expected {
+        return Err(TopicError::UnexpectedAccounts.into());
+    }
+    Ok(())
+}
+
+/// Saves a changed topic, enforcing and stamping its `min_action_interval` cooldown.
+fn save_action(topic: &mut Topic, topic_account: &AccountInfo) -> ProgramResult {
+    topic.record_action(Clock::get()?.unix_timestamp)?;
+    topic.save(topic_account)
+}
+
+/// Reads a token account backing a vote, which must be owned by the token program.
+fn token_account_data(acc: &AccountInfo) -> Result<(Pubkey, TokenAccountData), ProgramError> {
+    if *acc.owner != TOKEN_PROGRAM_ID {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+    Ok((
+        *acc.key,
+        TokenAccountData::unpack_from_slice(&acc.data.borrow())?,
+    ))
+}
+
+/// Reads `voter`'s weight from an oracle account, which must be owned by `authority`.
+fn oracle_weight(
+    acc: &AccountInfo,
+    authority: &Pubkey,
+    voter: &Pubkey,
+) -> Result<u64, ProgramError> {
+    if acc.owner != authority {
+        return Err(TopicError::OracleMismatch.into());
+    }
+    let oracle = OracleWeight::unpack_from_slice(&acc.data.borrow())?;
+    if oracle.voter != *voter {
+        return Err(TopicError::OracleMismatch.into());
+    }
+    Ok(oracle.weight)
+}
+
+/// Returns the option voted for and its new vote count: `opt_idx: u8 | vote_count: u32`.
+fn set_vote_receipt(opt_idx: OptIdx, vote_count: u32) {
+    let mut receipt = [0; 5];
+    receipt[0] = opt_idx.into();
+    pack_u32(vote_count, array_mut_ref![receipt, 1, 4]);
+    set_return_data(&receipt);
+}
+
+/// What a signed vote is backed by, besides the voter's own signature.
+#[derive(Clone, Copy, PartialEq)]
+enum VoteSource {
+    Wallet,
+    /// One token account, recorded so it cannot back another vote.
+    TokenAccount,
+    /// Every account after the voter (and before a co-signer) is a token account whose
+    /// balance weights the vote.
+    TokenBalances,
+}
 
 pub struct Processor {}
 impl Processor {
     pub fn process(program_id: &Pubkey, accounts: &[AccountInfo], input: &[u8]) -> ProgramResult {
         let i = TopicInstruction::unpack(input)?;
 
-        Ok(match i {
+        match i {
             TopicInstruction::CreateTopic {
                 topic_name,
                 option_name,
+                category,
+                config,
             } => {
                 msg!("process create topic");
-                Processor::process_create_topic(program_id, accounts, topic_name, option_name)?
+                Processor::process_create_topic(
+                    program_id,
+                    accounts,
+                    topic_name,
+                    option_name,
+                    &category,
+                    config,
+                )
+            }
+            TopicInstruction::CreateTopicIfMissing {
+                topic_name,
+                option_name,
+                category,
+                config,
+            } => {
+                msg!("process create topic if missing");
+                Processor::process_create_topic_if_missing(
+                    program_id,
+                    accounts,
+                    topic_name,
+                    option_name,
+                    &category,
+                    config,
+                )
             }
             TopicInstruction::AddOption { option_name } => {
                 msg!("process add option");
-                Processor::process_add_option(program_id, accounts, option_name)?
+                Processor::process_add_option(program_id, accounts, option_name)
             }
-            TopicInstruction::VoteTopic { opt_idx } => {
+            TopicInstruction::VoteTopic { opt_idx, nonce } => {
                 msg!("process vote topic");
-                Processor::process_vote(program_id, accounts, opt_idx)?
+                Processor::process_vote(program_id, accounts, opt_idx, nonce)
+            }
+            TopicInstruction::TokenVote { opt_idx } => {
+                msg!("process token vote");
+                Processor::process_token_vote(program_id, accounts, opt_idx)
+            }
+            TopicInstruction::QuadraticVote { opt_idx, amount } => {
+                msg!("process quadratic vote");
+                Processor::process_quadratic_vote(program_id, accounts, opt_idx, amount)
             }
             TopicInstruction::FinishTopic => {
                 msg!("process finish topic");
-                Processor::process_finish(program_id, accounts)?
+                Processor::process_finish(program_id, accounts)
+            }
+            TopicInstruction::ForceFinish => {
+                msg!("process force finish");
+                Processor::process_force_finish(program_id, accounts)
+            }
+            TopicInstruction::ResetTopic => {
+                msg!("process reset topic");
+                Processor::process_reset(program_id, accounts)
+            }
+            TopicInstruction::ReopenTopic => {
+                msg!("process reopen topic");
+                Processor::process_reopen(program_id, accounts)
+            }
+            TopicInstruction::AddVoter { voter, weight } => {
+                msg!("process add voter");
+                Processor::process_add_voter(program_id, accounts, &voter, weight)
+            }
+            TopicInstruction::BanVoter { voter } => {
+                msg!("process ban voter");
+                Processor::process_ban_voter(program_id, accounts, &voter)
+            }
+            TopicInstruction::CloseTopic => {
+                msg!("process close topic");
+                Processor::process_close(program_id, accounts)
             }
-        })
+            TopicInstruction::RecomputeCache => {
+                msg!("process recompute cache");
+                Processor::process_recompute_cache(program_id, accounts)
+            }
+            TopicInstruction::GetMyVote => {
+                msg!("process get my vote");
+                Processor::process_get_my_vote(program_id, accounts)
+            }
+            TopicInstruction::SetPaused { paused } => {
+                msg!("process set paused");
+                Processor::process_set_paused(program_id, accounts, paused)
+            }
+            TopicInstruction::AdminReset => {
+                msg!("process admin reset");
+                Processor::process_admin_reset(program_id, accounts)
+            }
+            TopicInstruction::CertifyResult => {
+                msg!("process certify result");
+                Processor::process_certify_result(program_id, accounts)
+            }
+            TopicInstruction::RetractVote => {
+                msg!("process retract vote");
+                Processor::process_retract_vote(program_id, accounts)
+            }
+            TopicInstruction::ChangeVote { opt_idx } => {
+                msg!("process change vote");
+                Processor::process_change_vote(program_id, accounts, opt_idx)
+            }
+            TopicInstruction::VoteMultiSource { opt_idx } => {
+                msg!("process vote multi source");
+                Processor::process_vote_multi_source(program_id, accounts, opt_idx)
+            }
+            TopicInstruction::SetQuorum { min_quorum } => {
+                msg!("process set quorum");
+                Processor::process_set_quorum(program_id, accounts, min_quorum)
+            }
+            TopicInstruction::LogTally => {
+                msg!("process log tally");
+                Processor::process_log_tally(program_id, accounts)
+            }
+            TopicInstruction::ReorderOptions { order } => {
+                msg!("process reorder options");
+                Processor::process_reorder_options(program_id, accounts, &order)
+            }
+            TopicInstruction::RenameOption { opt_idx, name } => {
+                msg!("process rename option");
+                Processor::process_rename_option(program_id, accounts, opt_idx, name)
+            }
+            TopicInstruction::AppendNote { note } => {
+                msg!("process append note");
+                Processor::process_append_note(program_id, accounts, note)
+            }
+            TopicInstruction::AnonVote { nullifier, opt_idx } => {
+                msg!("process anon vote");
+                Processor::process_anon_vote(program_id, accounts, &nullifier, opt_idx)
+            }
+        }
     }
 
     pub fn process_create_topic(
@@ -41,27 +237,75 @@ impl Processor {
         accounts: &[AccountInfo],
         topic_name: &str,
         option_name: &str,
+        category: &[u8; 32],
+        config: TopicConfig,
+    ) -> ProgramResult {
+        Processor::create(
+            program_id,
+            accounts,
+            (topic_name, option_name),
+            category,
+            config,
+            false,
+        )
+    }
+
+    pub fn process_create_topic_if_missing(
+        program_id: &Pubkey,
+        accounts: &[AccountInfo],
+        topic_name: &str,
+        option_name: &str,
+        category: &[u8; 32],
+        config: TopicConfig,
+    ) -> ProgramResult {
+        Processor::create(
+            program_id,
+            accounts,
+            (topic_name, option_name),
+            category,
+            config,
+            true,
+        )
+    }
+
+    /// Initializes a topic. With `if_missing`, a topic that already has this name and owner
+    /// is left untouched instead of failing.
+    fn create(
+        program_id: &Pubkey,
+        accounts: &[AccountInfo],
+        (topic_name, option_name): (&str, &str),
+        category: &[u8; 32],
+        config: TopicConfig,
+        if_missing: bool,
     ) -> ProgramResult {
+        check_accounts_len(accounts, 2)?;
         let accs_iter = &mut accounts.iter();
         let topic_account = next_account_info(accs_iter)?;
         let topic_owner = next_account_info(accs_iter)?;
 
-        if topic_account.owner != program_id {
-            return Err(ProgramError::IllegalOwner);
-        }
+        config.validate()?;
+        let topic = Topic::from_account_info(topic_account, program_id)?;
         if !topic_owner.is_signer {
             return Err(ProgramError::MissingRequiredSignature);
         }
-
-        let mut topic = Topic::unpack_from_slice(&topic_account.data.borrow())?;
         if !topic.name_is_empty() {
+            if if_missing
+                && topic.is_owner(topic_owner.key)
+                && str_unpack(&topic.name)? == topic_name
+            {
+                msg!("topic already exists");
+                return Ok(());
+            }
             return Err(ProgramError::AccountAlreadyInitialized);
         }
-        topic.set_name(topic_name);
+        // Only the name marks a topic as initialized; drop anything left in the rest of the data.
+        let mut topic = Topic::default();
+        topic.set_name(topic_name)?;
         topic.owner = *topic_owner.key;
+        topic.category = *category;
+        topic.config = config;
         topic.add_option(topic_account.key, option_name)?;
-        topic.pack_into_slice(&mut topic_account.data.borrow_mut());
-        Ok(())
+        save_action(&mut topic, topic_account)
     }
 
     pub fn process_add_option(
@@ -69,237 +313,2597 @@ impl Processor {
         accounts: &[AccountInfo],
         option_name: &str,
     ) -> ProgramResult {
+        check_accounts_len(accounts, 2)?;
         let accs_iter = &mut accounts.iter();
         let topic_account = next_account_info(accs_iter)?;
         let option_adder = next_account_info(accs_iter)?;
 
-        if topic_account.owner != program_id {
-            return Err(ProgramError::IllegalOwner);
-        }
+        let mut topic = Topic::from_account_info(topic_account, program_id)?;
         if !option_adder.is_signer {
             return Err(ProgramError::MissingRequiredSignature);
         }
-
-        let mut topic = Topic::unpack_from_slice(&topic_account.data.borrow())?;
-        if topic.name.is_empty() || topic.is_finished {
-            return Err(ProgramError::InvalidAccountData);
-        }
         topic.add_option(topic_account.key, option_name)?;
-        topic.pack_into_slice(&mut topic_account.data.borrow_mut());
-        Ok(())
+        save_action(&mut topic, topic_account)
     }
 
     pub fn process_vote(
         program_id: &Pubkey,
         accounts: &[AccountInfo],
-        opt_idx: u8,
+        opt_idx: OptIdx,
+        nonce: Option<u64>,
+    ) -> ProgramResult {
+        Processor::vote(
+            program_id,
+            accounts,
+            opt_idx,
+            VoteSource::Wallet,
+            nonce,
+            None,
+        )
+    }
+
+    pub fn process_token_vote(
+        program_id: &Pubkey,
+        accounts: &[AccountInfo],
+        opt_idx: OptIdx,
+    ) -> ProgramResult {
+        Processor::vote(
+            program_id,
+            accounts,
+            opt_idx,
+            VoteSource::TokenAccount,
+            None,
+            None,
+        )
+    }
+
+    pub fn process_vote_multi_source(
+        program_id: &Pubkey,
+        accounts: &[AccountInfo],
+        opt_idx: OptIdx,
+    ) -> ProgramResult {
+        Processor::vote(
+            program_id,
+            accounts,
+            opt_idx,
+            VoteSource::TokenBalances,
+            None,
+            None,
+        )
+    }
+
+    pub fn process_quadratic_vote(
+        program_id: &Pubkey,
+        accounts: &[AccountInfo],
+        opt_idx: OptIdx,
+        amount: u64,
+    ) -> ProgramResult {
+        Processor::vote(
+            program_id,
+            accounts,
+            opt_idx,
+            VoteSource::Wallet,
+            None,
+            Some(amount),
+        )
+    }
+
+    /// Records a signed vote backed by `source`. Oracle-weighted topics take the oracle account
+    /// after the voter's token accounts, and topics requiring a co-signer take it as the
+    /// last account. Quadratic topics take the committed `amount` and only accept votes that
+    /// carry one; token-weighted topics only accept `VoteSource::TokenBalances`.
+    fn vote(
+        program_id: &Pubkey,
+        accounts: &[AccountInfo],
+        opt_idx: OptIdx,
+        source: VoteSource,
+        nonce: Option<u64>,
+        amount: Option<u64>,
     ) -> ProgramResult {
+        // The exact count depends on the topic's config, but the topic and voter always come first.
+        if accounts.len() < 2 {
+            return Err(ProgramError::NotEnoughAccountKeys);
+        }
         let accs_iter = &mut accounts.iter();
         let topic_account = next_account_info(accs_iter)?;
+        let mut topic = Topic::from_account_info(topic_account, program_id)?;
+        let extras = topic.config.oracle_weighted as usize + topic.config.require_cosigner as usize;
+        let token_count = match source {
+            VoteSource::Wallet => 0,
+            VoteSource::TokenAccount => 1,
+            VoteSource::TokenBalances => accounts.len().saturating_sub(2 + extras).max(1),
+        };
+        check_accounts_len(accounts, 2 + token_count + extras)?;
         let voter = next_account_info(accs_iter)?;
+        let token_accounts = &accounts[2..2 + token_count];
+        let accs_iter = &mut accounts[2 + token_count..].iter();
 
-        if topic_account.owner != program_id {
-            return Err(ProgramError::IllegalOwner);
+        if !voter.is_signer {
+            return Err(ProgramError::MissingRequiredSignature);
+        }
+        let oracle_weight = match topic.config.oracle_weighted {
+            true => Some(oracle_weight(
+                next_account_info(accs_iter)?,
+                &topic.config.oracle_authority,
+                voter.key,
+            )?),
+            false => None,
+        };
+        if topic.config.require_cosigner {
+            let cosigner = next_account_info(accs_iter)?;
+            if !cosigner.is_signer || *cosigner.key != topic.config.cosigner {
+                return Err(ProgramError::MissingRequiredSignature);
+            }
+        }
+        if let Some(nonce) = nonce {
+            topic.use_nonce(voter.key, nonce)?;
+        }
+        let now = Clock::get()?.unix_timestamp;
+        topic.can_vote(voter.key, now)?;
+        if voter.lamports() < topic.config.min_voter_lamports {
+            return Err(TopicError::VoterBalanceTooLow.into());
+        }
+        if (topic.config.tally_mode == TallyMode::Quadratic) != amount.is_some() {
+            return Err(ProgramError::InvalidInstructionData);
+        }
+        if (topic.config.tally_mode == TallyMode::TokenWeight)
+            != (source == VoteSource::TokenBalances)
+        {
+            return Err(ProgramError::InvalidInstructionData);
+        }
+        if voter.lamports() < amount.unwrap_or(0) {
+            return Err(TopicError::VoterBalanceTooLow.into());
+        }
+        let vote_count = match source {
+            VoteSource::Wallet => topic.vote_with_receipt(opt_idx, voter.key, now)?,
+            VoteSource::TokenAccount => {
+                let (token_account, data) = token_account_data(&token_accounts[0])?;
+                if data.owner != *voter.key {
+                    return Err(ProgramError::IllegalOwner);
+                }
+                if data.mint != topic.config.token_mint {
+                    return Err(TopicError::TokenMintMismatch.into());
+                }
+                topic.vote_with_token_account(opt_idx, voter.key, &token_account, now)?;
+                topic.options[opt_idx.as_usize()].vote_count()
+            }
+            VoteSource::TokenBalances => {
+                let sources = token_accounts
+                    .iter()
+                    .map(token_account_data)
+                    .collect::<Result<Vec<_>, _>>()?;
+                topic.vote_with_token_balances(opt_idx, voter.key, &sources, now)?
+            }
+        };
+        if topic.config.weight_by_lamports {
+            topic.options[opt_idx.as_usize()].add_weight(voter.lamports() / LAMPORTS_PER_SOL)?;
+        }
+        if let Some(amount) = amount {
+            topic.options[opt_idx.as_usize()].add_weight(TallyMode::quadratic_weight(amount))?;
+        }
+        if topic.config.tally_mode == TallyMode::WhitelistWeight {
+            let weight = topic.whitelist_weight(voter.key);
+            topic.options[opt_idx.as_usize()].add_weight(weight)?;
         }
+        if let Some(weight) = oracle_weight {
+            topic.options[opt_idx.as_usize()].add_weight(weight)?;
+        }
+        if topic.config.auto_finish_on_quorum && topic.quorum_reached() {
+            msg!("quorum reached, finishing topic");
+            topic.finalize()?;
+        }
+        save_action(&mut topic, topic_account)?;
+
+        set_vote_receipt(opt_idx, vote_count);
+        Ok(())
+    }
+
+    pub fn process_retract_vote(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+        check_accounts_len(accounts, 2)?;
+        let accs_iter = &mut accounts.iter();
+        let topic_account = next_account_info(accs_iter)?;
+        let voter = next_account_info(accs_iter)?;
+
+        let mut topic = Topic::from_account_info(topic_account, program_id)?;
         if !voter.is_signer {
             return Err(ProgramError::MissingRequiredSignature);
         }
+        topic.retract_vote(voter.key, Clock::get()?.unix_timestamp)?;
+        save_action(&mut topic, topic_account)
+    }
 
-        let mut topic = Topic::unpack_from_slice(&topic_account.data.borrow())?;
-        if topic.name.is_empty() || topic.is_finished {
-            return Err(ProgramError::InvalidAccountData);
+    pub fn process_change_vote(
+        program_id: &Pubkey,
+        accounts: &[AccountInfo],
+        opt_idx: OptIdx,
+    ) -> ProgramResult {
+        check_accounts_len(accounts, 2)?;
+        let accs_iter = &mut accounts.iter();
+        let topic_account = next_account_info(accs_iter)?;
+        let voter = next_account_info(accs_iter)?;
+
+        let mut topic = Topic::from_account_info(topic_account, program_id)?;
+        if !voter.is_signer {
+            return Err(ProgramError::MissingRequiredSignature);
         }
-        topic.vote(opt_idx, voter.key)?;
-        topic.pack_into_slice(&mut topic_account.data.borrow_mut());
+        let vote_count = topic.change_vote(opt_idx, voter.key, Clock::get()?.unix_timestamp)?;
+        save_action(&mut topic, topic_account)?;
+
+        set_vote_receipt(opt_idx, vote_count);
         Ok(())
     }
 
-    pub fn process_finish(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+    pub fn process_reset(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+        check_accounts_len(accounts, 2)?;
         let accs_iter = &mut accounts.iter();
         let topic_account = next_account_info(accs_iter)?;
         let topic_owner = next_account_info(accs_iter)?;
 
-        if topic_account.owner != program_id {
-            return Err(ProgramError::IllegalOwner);
+        let mut topic = Topic::from_account_info(topic_account, program_id)?;
+        topic.verify_owner_signer(topic_owner)?;
+        if topic.is_sealed() {
+            return Err(TopicError::TopicSealed.into());
         }
-        if !topic_owner.is_signer {
-            return Err(ProgramError::MissingRequiredSignature);
-        }
-        let mut topic = Topic::unpack_from_slice(&topic_account.data.borrow())?;
-        if topic.name.is_empty() || topic.is_finished {
+        if topic.name_is_empty() || topic.is_finished {
             return Err(ProgramError::InvalidAccountData);
         }
-        if topic.owner != *topic_owner.key {
-            return Err(ProgramError::IllegalOwner);
-        }
-        topic.finalize()?;
-        topic.pack_into_slice(&mut topic_account.data.borrow_mut());
-        Ok(())
+        topic.reset();
+        save_action(&mut topic, topic_account)
     }
-}
-
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use crate::instruction::{add_option, create_topic, finish_topic, vote_topic};
-    use solana_program::{instruction::Instruction, system_program};
-    use solana_sdk::account::{create_is_signer_account_infos, Account as SolanaAccount};
 
-    fn do_process_instruction(
-        instruction: Instruction,
-        accounts: Vec<&mut SolanaAccount>,
+    pub fn process_reorder_options(
+        program_id: &Pubkey,
+        accounts: &[AccountInfo],
+        order: &[u8; MAX_OPTIONS],
     ) -> ProgramResult {
-        let mut meta = instruction
-            .accounts
-            .iter()
-            .zip(accounts)
-            .map(|(account_meta, account)| (&account_meta.pubkey, account_meta.is_signer, account))
-            .collect::<Vec<_>>();
+        check_accounts_len(accounts, 2)?;
+        let accs_iter = &mut accounts.iter();
+        let topic_account = next_account_info(accs_iter)?;
+        let topic_owner = next_account_info(accs_iter)?;
 
-        let account_infos = create_is_signer_account_infos(&mut meta);
-        Processor::process(&instruction.program_id, &account_infos, &instruction.data)
+        let mut topic = Topic::from_account_info(topic_account, program_id)?;
+        topic.verify_owner_signer(topic_owner)?;
+        if topic.name_is_empty() || topic.is_finished {
+            return Err(ProgramError::InvalidAccountData);
+        }
+        topic.reorder_options(order)?;
+        save_action(&mut topic, topic_account)
     }
 
-    struct TestSuite {
-        program_id: Pubkey,
-        topic_key: (Pubkey, SolanaAccount),
-        topic_owner: (Pubkey, SolanaAccount),
-    }
+    pub fn process_rename_option(
+        program_id: &Pubkey,
+        accounts: &[AccountInfo],
+        opt_idx: OptIdx,
+        name: &str,
+    ) -> ProgramResult {
+        check_accounts_len(accounts, 2)?;
+        let accs_iter = &mut accounts.iter();
+        let topic_account = next_account_info(accs_iter)?;
+        let topic_owner = next_account_info(accs_iter)?;
 
-    impl TestSuite {
-        fn new() -> TestSuite {
-            let pid = Pubkey::default();
-            TestSuite {
-                program_id: pid,
-                topic_key: Self::get_key_account(&pid, Topic::get_packed_len()),
-                topic_owner: Self::get_key_account(&system_program::ID, Topic::get_packed_len()),
-            }
+        let mut topic = Topic::from_account_info(topic_account, program_id)?;
+        topic.verify_owner_signer(topic_owner)?;
+        if topic.name_is_empty() || topic.is_finished {
+            return Err(ProgramError::InvalidAccountData);
         }
+        topic.rename_option(opt_idx, name)?;
+        save_action(&mut topic, topic_account)
+    }
 
-        fn topic_eq(&self, expect_topic: &Topic) -> Result<bool, ProgramError> {
-            let topic = Topic::unpack_from_slice(&self.topic_key.1.data)?;
-            Ok(topic.eq(expect_topic))
-        }
+    pub fn process_append_note(
+        program_id: &Pubkey,
+        accounts: &[AccountInfo],
+        note: [u8; NOTE_LEN],
+    ) -> ProgramResult {
+        check_accounts_len(accounts, 2)?;
+        let accs_iter = &mut accounts.iter();
+        let topic_account = next_account_info(accs_iter)?;
+        let topic_owner = next_account_info(accs_iter)?;
 
-        fn get_key_account(owner: &Pubkey, space: usize) -> (Pubkey, SolanaAccount) {
-            (
-                Pubkey::new_unique(),
-                SolanaAccount::new(10000, space, owner),
-            )
+        let mut topic = Topic::from_account_info(topic_account, program_id)?;
+        topic.verify_owner_signer(topic_owner)?;
+        if topic.name_is_empty() || !topic.is_finished {
+            return Err(ProgramError::InvalidAccountData);
         }
+        topic.note = note;
+        save_action(&mut topic, topic_account)
+    }
 
-        fn process_create_topic(&mut self, topic_name: &str, option_name: &str) -> ProgramResult {
-            let i = create_topic(
-                &self.program_id,
-                &self.topic_key.0,
-                &self.topic_owner.0,
-                topic_name,
-                option_name,
-            )?;
-            do_process_instruction(i, vec![&mut self.topic_key.1, &mut self.topic_owner.1])
-        }
+    pub fn process_certify_result(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+        check_accounts_len(accounts, 2)?;
+        let accs_iter = &mut accounts.iter();
+        let topic_account = next_account_info(accs_iter)?;
+        let topic_owner = next_account_info(accs_iter)?;
 
-        fn process_add_option(&mut self, option_name: &str) -> ProgramResult {
-            let i = add_option(
-                &self.program_id,
-                &self.topic_key.0,
-                &self.topic_owner.0,
-                option_name,
-            )?;
-            do_process_instruction(i, vec![&mut self.topic_key.1, &mut self.topic_owner.1])
-        }
+        let mut topic = Topic::from_account_info(topic_account, program_id)?;
+        topic.verify_owner_signer(topic_owner)?;
+        topic.certify()?;
+        save_action(&mut topic, topic_account)
+    }
 
-        fn process_vote(
-            &mut self,
-            option_idx: u8,
-            voter: &mut (Pubkey, SolanaAccount),
-        ) -> ProgramResult {
-            let i = vote_topic(&self.program_id, &self.topic_key.0, &voter.0, option_idx)?;
-            do_process_instruction(i, vec![&mut self.topic_key.1, &mut voter.1])
-        }
+    pub fn process_reopen(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+        check_accounts_len(accounts, 2)?;
+        let accs_iter = &mut accounts.iter();
+        let topic_account = next_account_info(accs_iter)?;
+        let topic_owner = next_account_info(accs_iter)?;
 
-        fn process_finish(&mut self) -> ProgramResult {
-            let i = finish_topic(&self.program_id, &self.topic_key.0, &self.topic_owner.0)?;
-            do_process_instruction(i, vec![&mut self.topic_key.1, &mut self.topic_owner.1])
+        let mut topic = Topic::from_account_info(topic_account, program_id)?;
+        topic.verify_owner_signer(topic_owner)?;
+        if topic.is_sealed() {
+            return Err(TopicError::TopicSealed.into());
         }
-
-        fn process_init_topic(
-            &mut self,
-            topic_name: &str,
-            first_opt: &str,
-            opts: Vec<&str>,
-        ) -> ProgramResult {
-            self.process_create_topic(topic_name, first_opt)?;
-            for opt in opts {
-                self.process_add_option(opt)?;
-            }
-            Ok(())
+        if topic.name_is_empty() || !topic.is_finished {
+            return Err(ProgramError::InvalidAccountData);
         }
+        topic.reset_result();
+        save_action(&mut topic, topic_account)
     }
 
-    #[test]
-    fn test_create_topic() {
-        let mut ts = TestSuite::new();
+    pub fn process_set_paused(
+        program_id: &Pubkey,
+        accounts: &[AccountInfo],
+        paused: bool,
+    ) -> ProgramResult {
+        check_accounts_len(accounts, 2)?;
+        let accs_iter = &mut accounts.iter();
+        let topic_account = next_account_info(accs_iter)?;
+        let topic_owner = next_account_info(accs_iter)?;
+
+        let mut topic = Topic::from_account_info(topic_account, program_id)?;
+        topic.verify_owner_signer(topic_owner)?;
+        if topic.name_is_empty() || topic.is_finished {
+            return Err(ProgramError::InvalidAccountData);
+        }
+        topic.is_paused = paused;
+        save_action(&mut topic, topic_account)
+    }
+
+    pub fn process_set_quorum(
+        program_id: &Pubkey,
+        accounts: &[AccountInfo],
+        min_quorum: u32,
+    ) -> ProgramResult {
+        check_accounts_len(accounts, 2)?;
+        let accs_iter = &mut accounts.iter();
+        let topic_account = next_account_info(accs_iter)?;
+        let topic_owner = next_account_info(accs_iter)?;
+
+        let mut topic = Topic::from_account_info(topic_account, program_id)?;
+        topic.verify_owner_signer(topic_owner)?;
+        if topic.name_is_empty() || topic.is_finished {
+            return Err(ProgramError::InvalidAccountData);
+        }
+        topic.set_quorum(min_quorum)?;
+        save_action(&mut topic, topic_account)
+    }
+
+    pub fn process_add_voter(
+        program_id: &Pubkey,
+        accounts: &[AccountInfo],
+        voter: &Pubkey,
+        weight: u64,
+    ) -> ProgramResult {
+        check_accounts_len(accounts, 2)?;
+        let accs_iter = &mut accounts.iter();
+        let topic_account = next_account_info(accs_iter)?;
+        let topic_owner = next_account_info(accs_iter)?;
+
+        let mut topic = Topic::from_account_info(topic_account, program_id)?;
+        topic.verify_owner_signer(topic_owner)?;
+        if topic.name_is_empty() || topic.is_finished {
+            return Err(ProgramError::InvalidAccountData);
+        }
+        topic.add_whitelisted(voter, weight)?;
+        save_action(&mut topic, topic_account)
+    }
+
+    pub fn process_ban_voter(
+        program_id: &Pubkey,
+        accounts: &[AccountInfo],
+        voter: &Pubkey,
+    ) -> ProgramResult {
+        check_accounts_len(accounts, 2)?;
+        let accs_iter = &mut accounts.iter();
+        let topic_account = next_account_info(accs_iter)?;
+        let topic_owner = next_account_info(accs_iter)?;
+
+        let mut topic = Topic::from_account_info(topic_account, program_id)?;
+        topic.verify_owner_signer(topic_owner)?;
+        if topic.name_is_empty() || topic.is_finished {
+            return Err(ProgramError::InvalidAccountData);
+        }
+        topic.ban_voter(voter)?;
+        save_action(&mut topic, topic_account)
+    }
+
+    /// Closes a finished topic, paying the configured fee share and refunding the rest.
+    pub fn process_close(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+        check_accounts_len(accounts, 4)?;
+        let accs_iter = &mut accounts.iter();
+        let topic_account = next_account_info(accs_iter)?;
+        let topic_owner = next_account_info(accs_iter)?;
+        let refund = next_account_info(accs_iter)?;
+        let fee_destination = next_account_info(accs_iter)?;
+
+        let topic = Topic::from_account_info(topic_account, program_id)?;
+        topic.verify_owner_signer(topic_owner)?;
+        if topic.name_is_empty() || !topic.is_finished {
+            return Err(ProgramError::InvalidAccountData);
+        }
+        if topic.config.fee_bps > 0 && *fee_destination.key != topic.config.fee_destination {
+            return Err(ProgramError::InvalidArgument);
+        }
+
+        let (fee, refund_lamports) = topic.config.fee_split(topic_account.lamports());
+        **fee_destination.try_borrow_mut_lamports()? = fee_destination
+            .lamports()
+            .checked_add(fee)
+            .ok_or(ProgramError::InvalidArgument)?;
+        **refund.try_borrow_mut_lamports()? = refund
+            .lamports()
+            .checked_add(refund_lamports)
+            .ok_or(ProgramError::InvalidArgument)?;
+        **topic_account.try_borrow_mut_lamports()? = 0;
+        topic_account.data.borrow_mut().fill(0);
+        Ok(())
+    }
+
+    /// Zeroes the topic account without reading it, so even data that no longer unpacks can be
+    /// recovered. The account is left uninitialized, ready to be created again.
+    pub fn process_admin_reset(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+        check_accounts_len(accounts, 2)?;
+        let accs_iter = &mut accounts.iter();
+        let topic_account = next_account_info(accs_iter)?;
+        let admin = next_account_info(accs_iter)?;
+
+        if topic_account.owner != program_id {
+            return Err(TopicError::WrongProgramOwner.into());
+        }
+        if !admin.is_signer {
+            return Err(ProgramError::MissingRequiredSignature);
+        }
+        if ADMIN != Some(*admin.key) {
+            return Err(TopicError::NotAdmin.into());
+        }
+        topic_account.data.borrow_mut().fill(0);
+        Ok(())
+    }
+
+    pub fn process_recompute_cache(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+        check_accounts_len(accounts, 1)?;
+        let accs_iter = &mut accounts.iter();
+        let topic_account = next_account_info(accs_iter)?;
+
+        let mut topic = Topic::from_account_info(topic_account, program_id)?;
+        if topic.name_is_empty() {
+            return Err(ProgramError::InvalidAccountData);
+        }
+        topic.recompute_cache();
+        topic.save(topic_account)
+    }
+
+    pub fn process_log_tally(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+        check_accounts_len(accounts, 1)?;
+        let accs_iter = &mut accounts.iter();
+        let topic_account = next_account_info(accs_iter)?;
+
+        let topic = Topic::from_account_info(topic_account, program_id)?;
+        if topic.name_is_empty() {
+            return Err(ProgramError::InvalidAccountData);
+        }
+        if !topic.results_visible() {
+            return Err(TopicError::ResultsHidden.into());
+        }
+        for (i, opt) in topic.options_iter() {
+            msg!("option {}: {} votes", i, opt.vote_count());
+        }
+        Ok(())
+    }
+
+    pub fn process_get_my_vote(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+        check_accounts_len(accounts, 2)?;
+        let accs_iter = &mut accounts.iter();
+        let topic_account = next_account_info(accs_iter)?;
+        let voter = next_account_info(accs_iter)?;
+
+        let topic = Topic::from_account_info(topic_account, program_id)?;
+        if !voter.is_signer {
+            return Err(ProgramError::MissingRequiredSignature);
+        }
+        if topic.name_is_empty() {
+            return Err(ProgramError::InvalidAccountData);
+        }
+        match topic.voted_option(voter.key) {
+            Some(idx) => msg!("MYVOTE={}", idx),
+            None => msg!("MYVOTE=none"),
+        }
+        Ok(())
+    }
+
+    pub fn process_anon_vote(
+        program_id: &Pubkey,
+        accounts: &[AccountInfo],
+        nullifier: &[u8; 32],
+        opt_idx: OptIdx,
+    ) -> ProgramResult {
+        check_accounts_len(accounts, 1)?;
+        let accs_iter = &mut accounts.iter();
+        let topic_account = next_account_info(accs_iter)?;
+
+        let mut topic = Topic::from_account_info(topic_account, program_id)?;
+        topic.check_accepting_votes(Clock::get()?.unix_timestamp)?;
+        topic.anon_vote(opt_idx, nullifier)?;
+        save_action(&mut topic, topic_account)
+    }
+
+    pub fn process_finish(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+        Processor::finish(program_id, accounts, false)
+    }
+
+    pub fn process_force_finish(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+        Processor::finish(program_id, accounts, true)
+    }
+
+    /// Finalizes the topic and returns the winner with the score that decided it:
+    /// `result_idx: u8 | score: u64`. The score is the vote count on unweighted topics and the
+    /// accumulated weight otherwise.
+    fn finish(program_id: &Pubkey, accounts: &[AccountInfo], bypass_quorum: bool) -> ProgramResult {
+        check_accounts_len(accounts, 2)?;
+        let accs_iter = &mut accounts.iter();
+        let topic_account = next_account_info(accs_iter)?;
+        let topic_owner = next_account_info(accs_iter)?;
+
+        let mut topic = Topic::from_account_info(topic_account, program_id)?;
+        topic.verify_owner_signer(topic_owner)?;
+        if !matches!(topic.state(), TopicState::Open | TopicState::Paused) {
+            return Err(ProgramError::InvalidAccountData);
+        }
+        if !topic.quorum_reached() {
+            if !bypass_quorum {
+                return Err(TopicError::QuorumNotReached.into());
+            }
+            msg!("quorum bypassed by owner");
+        }
+        topic.finalize()?;
+        save_action(&mut topic, topic_account)?;
+
+        let winner = topic
+            .options
+            .get(topic.result_idx as usize)
+            .ok_or(ProgramError::InvalidAccountData)?;
+        let mut result = [0; 9];
+        result[0] = topic.result_idx;
+        pack_u64(topic.option_score(winner), array_mut_ref![result, 1, 8]);
+        set_return_data(&result);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::instruction::{
+        add_option, add_voter, admin_reset, anon_vote, append_note, ban_voter, certify_result,
+        change_vote, close_topic, cosigned_vote_topic, create_topic, create_topic_if_missing,
+        finish_topic, force_finish, get_my_vote, log_tally, oracle_vote_topic, quadratic_vote,
+        recompute_cache, rename_option, reopen_topic, reorder_options, reset_topic, retract_vote,
+        set_paused, set_quorum, token_vote, vote_multi_source, vote_topic, vote_topic_with_nonce,
+    };
+    use crate::util::{unpack_u32, unpack_u64};
+    use arrayref::array_ref;
+    use solana_program::{
+        entrypoint::SUCCESS,
+        instruction::{AccountMeta, Instruction},
+        program_stubs::{set_syscall_stubs, SyscallStubs},
+        system_program,
+    };
+    use solana_sdk::account::{create_is_signer_account_infos, Account as SolanaAccount};
+    use std::{
+        cell::{Cell, RefCell},
+        sync::Once,
+    };
+
+    thread_local! {
+        static CLOCK_NOW: Cell<i64> = const { Cell::new(0) };
+        static RETURN_DATA: RefCell<Vec<u8>> = const { RefCell::new(Vec::new()) };
+        static LOGS: RefCell<Vec<String>> = const { RefCell::new(Vec::new()) };
+    }
+
+    struct TestSyscallStubs;
+    impl SyscallStubs for TestSyscallStubs {
+        fn sol_get_clock_sysvar(&self, var_addr: *mut u8) -> u64 {
+            let clock = Clock {
+                unix_timestamp: CLOCK_NOW.with(|now| now.get()),
+                ..Clock::default()
+            };
+            unsafe { *(var_addr as *mut Clock) = clock };
+            SUCCESS
+        }
+
+        fn sol_set_return_data(&self, data: &[u8]) {
+            RETURN_DATA.with(|d| *d.borrow_mut() = data.to_vec());
+        }
+
+        fn sol_log(&self, message: &str) {
+            LOGS.with(|l| l.borrow_mut().push(message.to_string()));
+        }
+    }
+
+    fn take_logs() -> Vec<String> {
+        LOGS.with(|l| l.take())
+    }
+
+    fn return_data() -> Vec<u8> {
+        RETURN_DATA.with(|d| d.borrow().clone())
+    }
+
+    fn set_clock(now: i64) {
+        CLOCK_NOW.with(|c| c.set(now));
+    }
+
+    fn do_process_instruction(
+        instruction: Instruction,
+        accounts: Vec<&mut SolanaAccount>,
+    ) -> ProgramResult {
+        let mut meta = instruction
+            .accounts
+            .iter()
+            .zip(accounts)
+            .map(|(account_meta, account)| (&account_meta.pubkey, account_meta.is_signer, account))
+            .collect::<Vec<_>>();
+
+        let account_infos = create_is_signer_account_infos(&mut meta);
+        Processor::process(&instruction.program_id, &account_infos, &instruction.data)
+    }
+
+    struct TestSuite {
+        program_id: Pubkey,
+        topic_key: (Pubkey, SolanaAccount),
+        topic_owner: (Pubkey, SolanaAccount),
+        category: [u8; 32],
+        config: TopicConfig,
+    }
+
+    impl TestSuite {
+        fn new() -> TestSuite {
+            static STUBS: Once = Once::new();
+            STUBS.call_once(|| {
+                set_syscall_stubs(Box::new(TestSyscallStubs));
+            });
+            let pid = Pubkey::default();
+            TestSuite {
+                program_id: pid,
+                topic_key: Self::get_key_account(&pid, Topic::get_packed_len()),
+                topic_owner: Self::get_key_account(&system_program::ID, Topic::get_packed_len()),
+                category: [0; 32],
+                config: TopicConfig::default(),
+            }
+        }
+
+        /// The topic this suite's owner would get from creating `name` with `options`.
+        fn expected_topic(&self, name: &str, options: &[&str]) -> Topic {
+            Topic::new_with_options(name, &self.topic_owner.0, &self.topic_key.0, options).unwrap()
+        }
+
+        fn topic_eq(&self, expect_topic: &Topic) -> Result<bool, ProgramError> {
+            let topic = Topic::unpack_from_slice(&self.topic_key.1.data)?;
+            Ok(topic.eq(expect_topic))
+        }
+
+        fn get_key_account(owner: &Pubkey, space: usize) -> (Pubkey, SolanaAccount) {
+            (
+                Pubkey::new_unique(),
+                SolanaAccount::new(10000, space, owner),
+            )
+        }
+
+        fn process_create_topic(&mut self, topic_name: &str, option_name: &str) -> ProgramResult {
+            let i = create_topic(
+                &self.program_id,
+                &self.topic_key.0,
+                &self.topic_owner.0,
+                topic_name,
+                option_name,
+                self.category,
+                self.config,
+            )?;
+            do_process_instruction(i, vec![&mut self.topic_key.1, &mut self.topic_owner.1])
+        }
+
+        fn process_create_topic_if_missing(
+            &mut self,
+            topic_name: &str,
+            option_name: &str,
+        ) -> ProgramResult {
+            let i = create_topic_if_missing(
+                &self.program_id,
+                &self.topic_key.0,
+                &self.topic_owner.0,
+                topic_name,
+                option_name,
+                self.category,
+                self.config,
+            )?;
+            do_process_instruction(i, vec![&mut self.topic_key.1, &mut self.topic_owner.1])
+        }
+
+        fn process_add_option(&mut self, option_name: &str) -> ProgramResult {
+            let i = add_option(
+                &self.program_id,
+                &self.topic_key.0,
+                &self.topic_owner.0,
+                option_name,
+            )?;
+            do_process_instruction(i, vec![&mut self.topic_key.1, &mut self.topic_owner.1])
+        }
+
+        fn process_vote(
+            &mut self,
+            option_idx: u8,
+            voter: &mut (Pubkey, SolanaAccount),
+        ) -> ProgramResult {
+            let i = vote_topic(
+                &self.program_id,
+                &self.topic_key.0,
+                &voter.0,
+                OptIdx(option_idx),
+            )?;
+            do_process_instruction(i, vec![&mut self.topic_key.1, &mut voter.1])
+        }
+
+        fn process_anon_vote(&mut self, option_idx: u8, nullifier: [u8; 32]) -> ProgramResult {
+            let i = anon_vote(
+                &self.program_id,
+                &self.topic_key.0,
+                nullifier,
+                OptIdx(option_idx),
+            )?;
+            do_process_instruction(i, vec![&mut self.topic_key.1])
+        }
+
+        fn process_reset(&mut self) -> ProgramResult {
+            let i = reset_topic(&self.program_id, &self.topic_key.0, &self.topic_owner.0)?;
+            do_process_instruction(i, vec![&mut self.topic_key.1, &mut self.topic_owner.1])
+        }
+
+        fn process_reorder_options(&mut self, order: [u8; MAX_OPTIONS]) -> ProgramResult {
+            let i = reorder_options(
+                &self.program_id,
+                &self.topic_key.0,
+                &self.topic_owner.0,
+                order,
+            )?;
+            do_process_instruction(i, vec![&mut self.topic_key.1, &mut self.topic_owner.1])
+        }
+
+        fn process_force_finish(&mut self) -> ProgramResult {
+            let i = force_finish(&self.program_id, &self.topic_key.0, &self.topic_owner.0)?;
+            do_process_instruction(i, vec![&mut self.topic_key.1, &mut self.topic_owner.1])
+        }
+
+        fn process_certify_result(&mut self) -> ProgramResult {
+            let i = certify_result(&self.program_id, &self.topic_key.0, &self.topic_owner.0)?;
+            do_process_instruction(i, vec![&mut self.topic_key.1, &mut self.topic_owner.1])
+        }
+
+        fn process_reopen(&mut self) -> ProgramResult {
+            let i = reopen_topic(&self.program_id, &self.topic_key.0, &self.topic_owner.0)?;
+            do_process_instruction(i, vec![&mut self.topic_key.1, &mut self.topic_owner.1])
+        }
+
+        fn process_rename_option(&mut self, opt_idx: u8, name: &str) -> ProgramResult {
+            let i = rename_option(
+                &self.program_id,
+                &self.topic_key.0,
+                &self.topic_owner.0,
+                OptIdx(opt_idx),
+                name,
+            )?;
+            do_process_instruction(i, vec![&mut self.topic_key.1, &mut self.topic_owner.1])
+        }
+
+        fn process_append_note(&mut self, note: &str) -> ProgramResult {
+            let i = append_note(
+                &self.program_id,
+                &self.topic_key.0,
+                &self.topic_owner.0,
+                note,
+            )?;
+            do_process_instruction(i, vec![&mut self.topic_key.1, &mut self.topic_owner.1])
+        }
+
+        fn process_set_paused(&mut self, paused: bool) -> ProgramResult {
+            let i = set_paused(
+                &self.program_id,
+                &self.topic_key.0,
+                &self.topic_owner.0,
+                paused,
+            )?;
+            do_process_instruction(i, vec![&mut self.topic_key.1, &mut self.topic_owner.1])
+        }
+
+        fn process_set_quorum(&mut self, min_quorum: u32) -> ProgramResult {
+            let i = set_quorum(
+                &self.program_id,
+                &self.topic_key.0,
+                &self.topic_owner.0,
+                min_quorum,
+            )?;
+            do_process_instruction(i, vec![&mut self.topic_key.1, &mut self.topic_owner.1])
+        }
+
+        fn process_add_voter(&mut self, voter: &Pubkey, weight: u64) -> ProgramResult {
+            let i = add_voter(
+                &self.program_id,
+                &self.topic_key.0,
+                &self.topic_owner.0,
+                voter,
+                weight,
+            )?;
+            do_process_instruction(i, vec![&mut self.topic_key.1, &mut self.topic_owner.1])
+        }
+
+        fn process_ban_voter(&mut self, voter: &Pubkey) -> ProgramResult {
+            let i = ban_voter(
+                &self.program_id,
+                &self.topic_key.0,
+                &self.topic_owner.0,
+                voter,
+            )?;
+            do_process_instruction(i, vec![&mut self.topic_key.1, &mut self.topic_owner.1])
+        }
+
+        fn process_log_tally(&mut self) -> ProgramResult {
+            let i = log_tally(&self.program_id, &self.topic_key.0)?;
+            do_process_instruction(i, vec![&mut self.topic_key.1])
+        }
+
+        fn process_finish(&mut self) -> ProgramResult {
+            let i = finish_topic(&self.program_id, &self.topic_key.0, &self.topic_owner.0)?;
+            do_process_instruction(i, vec![&mut self.topic_key.1, &mut self.topic_owner.1])
+        }
+
+        fn process_init_topic(
+            &mut self,
+            topic_name: &str,
+            first_opt: &str,
+            opts: Vec<&str>,
+        ) -> ProgramResult {
+            self.process_create_topic(topic_name, first_opt)?;
+            for opt in opts {
+                self.process_add_option(opt)?;
+            }
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_create_topic() {
+        let mut ts = TestSuite::new();
         let topic_name = "test_topic";
         let opt_name = "test_option";
         ts.process_create_topic(topic_name, opt_name).unwrap();
-        let mut expect = Topic::new("test_topic", &ts.topic_owner.0);
-        expect.add_option(&ts.topic_key.0, opt_name).unwrap();
+        let expect = ts.expected_topic("test_topic", &[opt_name]);
+        assert_eq!(Ok(true), ts.topic_eq(&expect));
+
+        assert_eq!(
+            Err(ProgramError::AccountAlreadyInitialized),
+            ts.process_create_topic("test_topic_2", "test_option")
+        )
+    }
+
+    #[test]
+    fn test_create_topic_with_category() {
+        let mut ts = TestSuite::new();
+        let category = [9; 32];
+        ts.category = category;
+        ts.process_create_topic("test_topic", "test_option")
+            .unwrap();
+        let mut expect = ts.expected_topic("test_topic", &["test_option"]);
+        expect.category = category;
+        assert_eq!(Ok(true), ts.topic_eq(&expect));
+    }
+
+    #[test]
+    fn test_distinct_names() {
+        let mut ts = TestSuite::new();
+        ts.process_init_topic("pizza", "pizza", vec!["pizza"])
+            .unwrap();
+
+        let mut ts = TestSuite::new();
+        ts.config.distinct_names = true;
+        assert_eq!(
+            Err(ProgramError::InvalidArgument),
+            ts.process_create_topic("pizza", "pizza")
+        );
+        ts.process_create_topic("pizza", "pasta").unwrap();
+        assert_eq!(
+            Err(ProgramError::InvalidArgument),
+            ts.process_add_option("pizza")
+        );
+        ts.process_add_option("salad").unwrap();
+    }
+
+    #[test]
+    fn test_add_option() {
+        let mut ts = TestSuite::new();
+        let topic_name = "test_topic";
+        let opt_name = "test_option";
+        ts.process_create_topic(topic_name, opt_name).unwrap();
+        let mut expect_topic = ts.expected_topic(topic_name, &[opt_name]);
+        let opt_name = "test_option2";
+        ts.process_add_option(opt_name).unwrap();
+        expect_topic.add_option(&ts.topic_key.0, opt_name).unwrap();
+
+        assert_eq!(Ok(true), ts.topic_eq(&expect_topic))
+    }
+
+    #[test]
+    fn test_vote() {
+        let mut ts = TestSuite::new();
+        let topic_name = "test_topic";
+        let opt_name = "test_option";
+        let opt_name2 = "test_option2";
+        ts.process_init_topic(topic_name, opt_name, vec![opt_name2])
+            .unwrap();
+        let mut expect_topic = ts.expected_topic(topic_name, &[opt_name, opt_name2]);
+
+        let mut key_acc = TestSuite::get_key_account(&system_program::ID, 100);
+        set_clock(1_600_000_000);
+        ts.process_vote(0, &mut key_acc).unwrap();
+        expect_topic
+            .vote(OptIdx(0), &key_acc.0, 1_600_000_000)
+            .unwrap();
+        expect_topic.last_action_at = 1_600_000_000;
+        assert_eq!(Ok(true), ts.topic_eq(&expect_topic))
+    }
+
+    #[test]
+    fn test_vote_records_time() {
+        let mut ts = TestSuite::new();
+        ts.process_init_topic("test_topic", "test_option", vec!["test_option2"])
+            .unwrap();
+        for (opt_idx, now) in [(1, 2_000), (0, 1_000), (1, 3_000)] {
+            let mut key_acc = TestSuite::get_key_account(&system_program::ID, 100);
+            set_clock(now);
+            ts.process_vote(opt_idx, &mut key_acc).unwrap();
+        }
+        let topic = Topic::unpack_from_slice(&ts.topic_key.1.data).unwrap();
+        assert_eq!(Some(1_000), topic.first_voter_time());
+        assert_eq!(Some(3_000), topic.last_voter_time());
+    }
+
+    #[test]
+    fn test_finish_topic() {
+        let mut ts = TestSuite::new();
+        let topic_name = "test_topic";
+        let opt_name = "test_option";
+        let opt_name2 = "test_option2";
+        ts.process_init_topic(topic_name, opt_name, vec![opt_name2])
+            .unwrap();
+        let mut expect_topic = ts.expected_topic(topic_name, &[opt_name, opt_name2]);
+        let mut key_acc = TestSuite::get_key_account(&system_program::ID, 100);
+        set_clock(0);
+        ts.process_vote(1, &mut key_acc).unwrap();
+        expect_topic.vote(OptIdx(1), &key_acc.0, 0).unwrap();
+        ts.process_finish().unwrap();
+        expect_topic.is_finished = true;
+        expect_topic.result_idx = 1;
+        assert_eq!(Ok(true), ts.topic_eq(&expect_topic))
+    }
+
+    #[test]
+    fn test_anon_vote() {
+        let mut ts = TestSuite::new();
+        let topic_name = "test_topic";
+        let opt_name = "test_option";
+        let opt_name2 = "test_option2";
+        ts.process_init_topic(topic_name, opt_name, vec![opt_name2])
+            .unwrap();
+        let mut expect_topic = ts.expected_topic(topic_name, &[opt_name, opt_name2]);
+
+        let nullifier = [1; 32];
+        ts.process_anon_vote(1, nullifier).unwrap();
+        expect_topic.anon_vote(OptIdx(1), &nullifier).unwrap();
+        assert_eq!(Ok(true), ts.topic_eq(&expect_topic));
+
+        assert_eq!(
+            Err(ProgramError::InvalidArgument),
+            ts.process_anon_vote(0, nullifier)
+        );
+        assert_eq!(Ok(true), ts.topic_eq(&expect_topic));
+    }
+
+    #[test]
+    fn test_anon_vote_not_accepting() {
+        let mut ts = TestSuite::new();
+        ts.config.deadline = 1_000;
+        ts.process_init_topic("test_topic", "test_option", vec![])
+            .unwrap();
+        set_clock(1_001);
+        assert_eq!(
+            Err(TopicError::DeadlinePassed.into()),
+            ts.process_anon_vote(0, [1; 32])
+        );
+        set_clock(1_000);
+        ts.process_anon_vote(0, [1; 32]).unwrap();
+
+        ts.process_finish().unwrap();
+        assert_eq!(
+            Err(TopicError::AlreadyFinished.into()),
+            ts.process_anon_vote(0, [2; 32])
+        );
+    }
+
+    #[test]
+    fn test_log_tally_visibility() {
+        let mut ts = TestSuite::new();
+        ts.process_init_topic("test_topic", "test_option", vec!["test_option2"])
+            .unwrap();
+        ts.process_log_tally().unwrap();
+
+        let mut ts = TestSuite::new();
+        ts.config.hide_results_until_finish = true;
+        ts.process_init_topic("test_topic", "test_option", vec!["test_option2"])
+            .unwrap();
+        assert_eq!(
+            Err(TopicError::ResultsHidden.into()),
+            ts.process_log_tally()
+        );
+        ts.process_finish().unwrap();
+        ts.process_log_tally().unwrap();
+    }
+
+    #[test]
+    fn test_lock_options_on_first_vote() {
+        let mut ts = TestSuite::new();
+        ts.process_init_topic("test_topic", "test_option", vec!["test_option2"])
+            .unwrap();
+        let mut key_acc = TestSuite::get_key_account(&system_program::ID, 100);
+        ts.process_vote(0, &mut key_acc).unwrap();
+        ts.process_add_option("test_option3").unwrap();
+
+        let mut ts = TestSuite::new();
+        ts.config.lock_options_on_first_vote = true;
+        ts.process_init_topic("test_topic", "test_option", vec!["test_option2"])
+            .unwrap();
+        let mut key_acc = TestSuite::get_key_account(&system_program::ID, 100);
+        ts.process_vote(0, &mut key_acc).unwrap();
+        assert_eq!(
+            Err(TopicError::OptionsLocked.into()),
+            ts.process_add_option("test_option3")
+        );
+    }
+
+    #[test]
+    fn test_min_voter_lamports() {
+        let mut ts = TestSuite::new();
+        ts.config.min_voter_lamports = 5000;
+        ts.process_init_topic("test_topic", "test_option", vec!["test_option2"])
+            .unwrap();
+        let mut poor_voter = (
+            Pubkey::new_unique(),
+            SolanaAccount::new(4999, 0, &system_program::ID),
+        );
+        assert_eq!(
+            Err(TopicError::VoterBalanceTooLow.into()),
+            ts.process_vote(0, &mut poor_voter)
+        );
+        let mut key_acc = TestSuite::get_key_account(&system_program::ID, 100);
+        ts.process_vote(0, &mut key_acc).unwrap();
+    }
+
+    #[test]
+    fn test_reset_topic() {
+        let mut ts = TestSuite::new();
+        ts.process_init_topic("test_topic", "test_option", vec!["test_option2"])
+            .unwrap();
+        let mut key_acc = TestSuite::get_key_account(&system_program::ID, 100);
+        ts.process_vote(0, &mut key_acc).unwrap();
+        ts.process_reset().unwrap();
+        let mut expect_topic = Topic::new("test_topic", &ts.topic_owner.0);
+        expect_topic.options_ever_added = 2;
+        assert_eq!(Ok(true), ts.topic_eq(&expect_topic));
+
+        ts.process_add_option("test_option3").unwrap();
+        ts.process_finish().unwrap();
+        assert_eq!(Err(ProgramError::InvalidAccountData), ts.process_reset());
+    }
+
+    #[test]
+    fn test_topic_account_not_owned_by_program() {
+        let mut ts = TestSuite::new();
+        ts.topic_key.1.owner = Pubkey::new_unique();
+        assert_eq!(
+            Err(TopicError::WrongProgramOwner.into()),
+            ts.process_create_topic("test_topic", "test_option")
+        );
+
+        let mut ts = TestSuite::new();
+        ts.process_init_topic("test_topic", "test_option", vec!["test_option2"])
+            .unwrap();
+        ts.topic_key.1.owner = Pubkey::new_unique();
+        assert_eq!(
+            Err(TopicError::WrongProgramOwner.into()),
+            ts.process_add_option("test_option3")
+        );
+        let mut key_acc = TestSuite::get_key_account(&system_program::ID, 100);
+        assert_eq!(
+            Err(TopicError::WrongProgramOwner.into()),
+            ts.process_vote(0, &mut key_acc)
+        );
+        assert_eq!(
+            Err(TopicError::WrongProgramOwner.into()),
+            ts.process_finish()
+        );
+    }
+
+    #[test]
+    fn test_reorder_options() {
+        let mut ts = TestSuite::new();
+        ts.process_init_topic("test_topic", "a", vec!["b", "c"])
+            .unwrap();
+        ts.process_reorder_options([1, 2, 0, 3, 4, 5, 6, 7, 8, 9])
+            .unwrap();
+        let mut expect_topic = ts.expected_topic("test_topic", &["a", "b", "c"]);
+        expect_topic
+            .reorder_options(&[1, 2, 0, 3, 4, 5, 6, 7, 8, 9])
+            .unwrap();
+        assert_eq!(Ok(true), ts.topic_eq(&expect_topic));
+        assert_eq!(
+            Some(&expect_topic.options[2]),
+            expect_topic.option_by_belongs_idx(0)
+        );
+
+        assert_eq!(
+            Err(ProgramError::InvalidArgument),
+            ts.process_reorder_options([1, 1, 0, 3, 4, 5, 6, 7, 8, 9])
+        );
+        assert_eq!(Ok(true), ts.topic_eq(&expect_topic));
+    }
+
+    #[test]
+    fn test_vote_return_data() {
+        let mut ts = TestSuite::new();
+        ts.process_init_topic("test_topic", "test_option", vec!["test_option2"])
+            .unwrap();
+        for expect_count in 1..=2 {
+            let mut key_acc = TestSuite::get_key_account(&system_program::ID, 100);
+            ts.process_vote(1, &mut key_acc).unwrap();
+            let data = return_data();
+            assert_eq!(5, data.len());
+            assert_eq!(1, data[0]);
+            assert_eq!(expect_count, unpack_u32(array_ref![data, 1, 4]));
+        }
+    }
+
+    #[test]
+    fn test_force_finish_below_quorum() {
+        let mut ts = TestSuite::new();
+        ts.config.min_quorum = 2;
+        ts.process_init_topic("test_topic", "test_option", vec!["test_option2"])
+            .unwrap();
+        let mut key_acc = TestSuite::get_key_account(&system_program::ID, 100);
+        ts.process_vote(1, &mut key_acc).unwrap();
+        assert_eq!(
+            Err(TopicError::QuorumNotReached.into()),
+            ts.process_finish()
+        );
+
+        ts.process_force_finish().unwrap();
+        let topic = Topic::unpack_from_slice(&ts.topic_key.1.data).unwrap();
+        assert!(topic.is_finished);
+        assert_eq!(1, topic.result_idx);
+    }
+
+    #[test]
+    fn test_weight_by_lamports() {
+        let mut ts = TestSuite::new();
+        ts.config.weight_by_lamports = true;
+        ts.process_init_topic("test_topic", "test_option", vec!["test_option2"])
+            .unwrap();
+        for _ in 0..2 {
+            let mut small_voter = (
+                Pubkey::new_unique(),
+                SolanaAccount::new(LAMPORTS_PER_SOL, 0, &system_program::ID),
+            );
+            ts.process_vote(0, &mut small_voter).unwrap();
+        }
+        let mut whale = (
+            Pubkey::new_unique(),
+            SolanaAccount::new(5 * LAMPORTS_PER_SOL, 0, &system_program::ID),
+        );
+        ts.process_vote(1, &mut whale).unwrap();
+
+        ts.process_finish().unwrap();
+        let topic = Topic::unpack_from_slice(&ts.topic_key.1.data).unwrap();
+        assert_eq!(2, topic.option_score(&topic.options[0]));
+        assert_eq!(5, topic.option_score(&topic.options[1]));
+        assert_eq!(1, topic.result_idx);
+    }
+
+    #[test]
+    fn test_reopen_topic() {
+        let mut ts = TestSuite::new();
+        ts.process_init_topic("test_topic", "test_option", vec!["test_option2"])
+            .unwrap();
+        assert_eq!(Err(ProgramError::InvalidAccountData), ts.process_reopen());
+        ts.process_finish().unwrap();
+        ts.process_reopen().unwrap();
+        let topic = Topic::unpack_from_slice(&ts.topic_key.1.data).unwrap();
+        assert!(!topic.is_finished);
+        ts.process_add_option("test_option3").unwrap();
+    }
+
+    #[test]
+    fn test_sealed_after_finish() {
+        let mut ts = TestSuite::new();
+        ts.config.sealed_after_finish = true;
+        ts.process_init_topic("test_topic", "test_option", vec!["test_option2"])
+            .unwrap();
+        ts.process_reset().unwrap();
+        ts.process_add_option("test_option").unwrap();
+        ts.process_finish().unwrap();
+        assert_eq!(Err(TopicError::TopicSealed.into()), ts.process_reopen());
+        assert_eq!(Err(TopicError::TopicSealed.into()), ts.process_reset());
+    }
+
+    #[test]
+    fn test_account_count() {
+        let mut ts = TestSuite::new();
+        ts.process_init_topic("test_topic", "test_option", vec!["test_option2"])
+            .unwrap();
+
+        let mut i = finish_topic(&ts.program_id, &ts.topic_key.0, &ts.topic_owner.0).unwrap();
+        i.accounts.truncate(1);
+        assert_eq!(
+            Err(ProgramError::NotEnoughAccountKeys),
+            do_process_instruction(i, vec![&mut ts.topic_key.1])
+        );
+
+        let mut i = vote_topic(
+            &ts.program_id,
+            &ts.topic_key.0,
+            &ts.topic_owner.0,
+            OptIdx(0),
+        )
+        .unwrap();
+        let mut extra = TestSuite::get_key_account(&system_program::ID, 100);
+        i.accounts.push(AccountMeta::new(extra.0, false));
+        assert_eq!(
+            Err(TopicError::UnexpectedAccounts.into()),
+            do_process_instruction(
+                i,
+                vec![&mut ts.topic_key.1, &mut ts.topic_owner.1, &mut extra.1]
+            )
+        );
+    }
+
+    #[test]
+    fn test_rename_option() {
+        let mut ts = TestSuite::new();
+        ts.process_init_topic("test_topic", "test_opton", vec!["test_option2"])
+            .unwrap();
+        ts.process_rename_option(0, "test_option").unwrap();
+        let expect_topic = ts.expected_topic("test_topic", &["test_option", "test_option2"]);
+        assert_eq!(Ok(true), ts.topic_eq(&expect_topic));
+
+        let mut key_acc = TestSuite::get_key_account(&system_program::ID, 100);
+        ts.process_vote(1, &mut key_acc).unwrap();
+        assert_eq!(
+            Err(TopicError::OptionHasVotes.into()),
+            ts.process_rename_option(1, "test_option3")
+        );
+        ts.process_finish().unwrap();
+        assert_eq!(
+            Err(ProgramError::InvalidAccountData),
+            ts.process_rename_option(0, "test_option4")
+        );
+    }
+
+    /// Packed topic size: 26664 bytes with 10 options of 30 voters each. Any layout change
+    /// has to update this on purpose.
+    const TOPIC_ACCOUNT_SIZE: usize = 26_664;
+
+    #[test]
+    fn test_topic_account_size() {
+        let mut ts = TestSuite::new();
+        ts.process_init_topic("test_topic", "test_option", vec![])
+            .unwrap();
+        let mut key_acc = TestSuite::get_key_account(&system_program::ID, 100);
+        ts.process_vote(0, &mut key_acc).unwrap();
+        assert_eq!(TOPIC_ACCOUNT_SIZE, ts.topic_key.1.data.len());
+        assert_eq!(TOPIC_ACCOUNT_SIZE, Topic::get_packed_len());
+    }
+
+    #[test]
+    fn test_append_note() {
+        let mut ts = TestSuite::new();
+        ts.process_init_topic("test_topic", "test_option", vec![])
+            .unwrap();
+        assert_eq!(
+            Err(ProgramError::InvalidAccountData),
+            ts.process_append_note("certified")
+        );
+
+        ts.process_finish().unwrap();
+        ts.process_append_note("certified").unwrap();
+        let topic = Topic::unpack_from_slice(&ts.topic_key.1.data).unwrap();
+        assert_eq!(b"certified", &topic.note[..9]);
+        assert_eq!([0; NOTE_LEN - 9], topic.note[9..]);
+
+        ts.process_reopen().unwrap();
+        let topic = Topic::unpack_from_slice(&ts.topic_key.1.data).unwrap();
+        assert_eq!([0; NOTE_LEN], topic.note);
+    }
+
+    #[test]
+    fn test_create_topic_names_with_delimiter() {
+        for (topic_name, option_name) in [("to|pic", "op|tion"), ("topic", "|"), ("|", "option")] {
+            let mut ts = TestSuite::new();
+            assert_eq!(
+                Err(ProgramError::InvalidArgument),
+                ts.process_create_topic(topic_name, option_name)
+            );
+            let topic = Topic::unpack_from_slice(&ts.topic_key.1.data).unwrap();
+            assert_eq!(TopicState::Uninitialized, topic.state());
+        }
+    }
+
+    #[test]
+    fn test_long_names_rejected() {
+        let mut ts = TestSuite::new();
+        let long_name = "a".repeat(100);
+        let mut i = create_topic(
+            &ts.program_id,
+            &ts.topic_key.0,
+            &ts.topic_owner.0,
+            "test_topic",
+            "test_option",
+            ts.category,
+            ts.config,
+        )
+        .unwrap();
+        i.data = TopicInstruction::CreateTopic {
+            topic_name: &long_name,
+            option_name: "test_option",
+            category: ts.category,
+            config: ts.config,
+        }
+        .pack()
+        .unwrap();
+        assert_eq!(
+            Err(ProgramError::InvalidArgument),
+            do_process_instruction(i, vec![&mut ts.topic_key.1, &mut ts.topic_owner.1])
+        );
+
+        ts.process_init_topic("test_topic", "test_option", vec![])
+            .unwrap();
+        let mut i = add_option(
+            &ts.program_id,
+            &ts.topic_key.0,
+            &ts.topic_owner.0,
+            "test_option2",
+        )
+        .unwrap();
+        i.data = TopicInstruction::AddOption {
+            option_name: &long_name,
+        }
+        .pack()
+        .unwrap();
+        assert_eq!(
+            Err(ProgramError::InvalidArgument),
+            do_process_instruction(i, vec![&mut ts.topic_key.1, &mut ts.topic_owner.1])
+        );
+
+        let mut i = rename_option(
+            &ts.program_id,
+            &ts.topic_key.0,
+            &ts.topic_owner.0,
+            OptIdx(0),
+            "renamed",
+        )
+        .unwrap();
+        i.data = TopicInstruction::RenameOption {
+            opt_idx: OptIdx(0),
+            name: &long_name,
+        }
+        .pack()
+        .unwrap();
+        assert_eq!(
+            Err(ProgramError::InvalidArgument),
+            do_process_instruction(i, vec![&mut ts.topic_key.1, &mut ts.topic_owner.1])
+        );
+    }
+
+    #[test]
+    fn test_empty_accounts() {
+        let program_id = Pubkey::new_unique();
+        let voter = Pubkey::new_unique();
+        let instructions = [
+            TopicInstruction::CreateTopic {
+                topic_name: "topic",
+                option_name: "option",
+                category: [1; 32],
+                config: TopicConfig::default(),
+            },
+            TopicInstruction::AddOption {
+                option_name: "option",
+            },
+            TopicInstruction::VoteTopic {
+                opt_idx: OptIdx(0),
+                nonce: None,
+            },
+            TopicInstruction::FinishTopic,
+            TopicInstruction::LogTally,
+            TopicInstruction::ResetTopic,
+            TopicInstruction::ReopenTopic,
+            TopicInstruction::BanVoter { voter },
+            TopicInstruction::CloseTopic,
+            TopicInstruction::SetPaused { paused: true },
+            TopicInstruction::TokenVote { opt_idx: OptIdx(0) },
+            TopicInstruction::AddVoter { voter, weight: 3 },
+            TopicInstruction::QuadraticVote {
+                opt_idx: OptIdx(0),
+                amount: 9,
+            },
+            TopicInstruction::AnonVote {
+                nullifier: [2; 32],
+                opt_idx: OptIdx(0),
+            },
+            TopicInstruction::ReorderOptions {
+                order: [0; MAX_OPTIONS],
+            },
+            TopicInstruction::ForceFinish,
+            TopicInstruction::RenameOption {
+                opt_idx: OptIdx(0),
+                name: "renamed",
+            },
+            TopicInstruction::AppendNote {
+                note: [0; NOTE_LEN],
+            },
+            TopicInstruction::CreateTopicIfMissing {
+                topic_name: "topic",
+                option_name: "option",
+                category: [1; 32],
+                config: TopicConfig::default(),
+            },
+            TopicInstruction::RecomputeCache,
+            TopicInstruction::GetMyVote,
+            TopicInstruction::SetQuorum { min_quorum: 5 },
+            TopicInstruction::VoteMultiSource { opt_idx: OptIdx(0) },
+            TopicInstruction::RetractVote,
+            TopicInstruction::ChangeVote { opt_idx: OptIdx(0) },
+            TopicInstruction::AdminReset,
+            TopicInstruction::CertifyResult,
+        ];
+        for i in &instructions {
+            assert_eq!(
+                Err(ProgramError::NotEnoughAccountKeys),
+                Processor::process(&program_id, &[], &i.pack().unwrap()),
+                "{:?}",
+                i
+            );
+        }
+    }
+
+    #[test]
+    fn test_certify_result() {
+        let mut ts = TestSuite::new();
+        ts.process_init_topic("test_topic", "test_option", vec![])
+            .unwrap();
+        assert_eq!(
+            Err(ProgramError::InvalidAccountData),
+            ts.process_certify_result()
+        );
+
+        ts.process_finish().unwrap();
+        let topic = Topic::unpack_from_slice(&ts.topic_key.1.data).unwrap();
+        assert!(topic.is_finished);
+        assert!(!topic.result_is_final());
+
+        ts.process_certify_result().unwrap();
+        let topic = Topic::unpack_from_slice(&ts.topic_key.1.data).unwrap();
+        assert!(topic.result_is_final());
+
+        ts.process_reopen().unwrap();
+        let topic = Topic::unpack_from_slice(&ts.topic_key.1.data).unwrap();
+        assert!(!topic.result_is_final());
+    }
+
+    #[test]
+    fn test_create_topic_if_missing() {
+        let mut ts = TestSuite::new();
+        ts.process_create_topic_if_missing("test_topic", "test_option")
+            .unwrap();
+        let expect = ts.expected_topic("test_topic", &["test_option"]);
+        assert_eq!(Ok(true), ts.topic_eq(&expect));
+
+        // a retried create leaves the existing topic untouched
+        ts.process_create_topic_if_missing("test_topic", "other_option")
+            .unwrap();
+        assert_eq!(Ok(true), ts.topic_eq(&expect));
+
+        assert_eq!(
+            Err(ProgramError::AccountAlreadyInitialized),
+            ts.process_create_topic_if_missing("test_topic_2", "test_option")
+        );
+
+        let mut other_owner = TestSuite::get_key_account(&system_program::ID, 0);
+        let i = create_topic_if_missing(
+            &ts.program_id,
+            &ts.topic_key.0,
+            &other_owner.0,
+            "test_topic",
+            "test_option",
+            ts.category,
+            ts.config,
+        )
+        .unwrap();
+        assert_eq!(
+            Err(ProgramError::AccountAlreadyInitialized),
+            do_process_instruction(i, vec![&mut ts.topic_key.1, &mut other_owner.1])
+        );
         assert_eq!(Ok(true), ts.topic_eq(&expect));
+    }
+
+    #[test]
+    fn test_max_voters_per_option() {
+        let mut ts = TestSuite::new();
+        ts.config.max_voters_per_option = 31;
+        assert_eq!(
+            Err(ProgramError::InvalidArgument),
+            ts.process_create_topic("test_topic", "test_option")
+        );
+
+        ts.config.max_voters_per_option = 2;
+        ts.process_init_topic("test_topic", "test_option", vec![])
+            .unwrap();
+        for _ in 0..2 {
+            let mut key_acc = TestSuite::get_key_account(&system_program::ID, 100);
+            ts.process_vote(0, &mut key_acc).unwrap();
+        }
+        let mut key_acc = TestSuite::get_key_account(&system_program::ID, 100);
+        assert_eq!(
+            Err(TopicError::OptionFull.into()),
+            ts.process_vote(0, &mut key_acc)
+        );
+    }
+
+    #[test]
+    fn test_ban_voter() {
+        let mut ts = TestSuite::new();
+        ts.process_init_topic("test_topic", "test_option", vec![])
+            .unwrap();
+        let mut banned = TestSuite::get_key_account(&system_program::ID, 100);
+        ts.process_ban_voter(&banned.0).unwrap();
+        assert_eq!(
+            Err(TopicError::VoterBanned.into()),
+            ts.process_vote(0, &mut banned)
+        );
+
+        let mut key_acc = TestSuite::get_key_account(&system_program::ID, 100);
+        ts.process_vote(0, &mut key_acc).unwrap();
+        let topic = Topic::unpack_from_slice(&ts.topic_key.1.data).unwrap();
+        assert_eq!(1, topic.total_votes());
+    }
+
+    #[test]
+    fn test_close_topic_fee_split() {
+        for (fee_bps, expect_fee) in [(0, 0), (250, 250), (10_000, 10_000)] {
+            let mut ts = TestSuite::new();
+            let mut fee_acc = TestSuite::get_key_account(&system_program::ID, 0);
+            let mut refund_acc = TestSuite::get_key_account(&system_program::ID, 0);
+            ts.config.fee_bps = fee_bps;
+            ts.config.fee_destination = fee_acc.0;
+            ts.process_init_topic("test_topic", "test_option", vec![])
+                .unwrap();
+            let i = close_topic(
+                &ts.program_id,
+                &ts.topic_key.0,
+                &ts.topic_owner.0,
+                &refund_acc.0,
+                &fee_acc.0,
+            )
+            .unwrap();
+            assert_eq!(
+                Err(ProgramError::InvalidAccountData),
+                do_process_instruction(
+                    i.clone(),
+                    vec![
+                        &mut ts.topic_key.1,
+                        &mut ts.topic_owner.1,
+                        &mut refund_acc.1,
+                        &mut fee_acc.1,
+                    ],
+                )
+            );
+
+            ts.process_finish().unwrap();
+            let topic_lamports = ts.topic_key.1.lamports;
+            do_process_instruction(
+                i,
+                vec![
+                    &mut ts.topic_key.1,
+                    &mut ts.topic_owner.1,
+                    &mut refund_acc.1,
+                    &mut fee_acc.1,
+                ],
+            )
+            .unwrap();
+            assert_eq!(0, ts.topic_key.1.lamports);
+            assert!(ts.topic_key.1.data.iter().all(|&b| b == 0));
+            assert_eq!(10_000 + expect_fee, fee_acc.1.lamports);
+            assert_eq!(10_000 + topic_lamports - expect_fee, refund_acc.1.lamports);
+        }
+    }
+
+    #[test]
+    fn test_close_topic_wrong_fee_destination() {
+        let mut ts = TestSuite::new();
+        let mut fee_acc = TestSuite::get_key_account(&system_program::ID, 0);
+        let mut refund_acc = TestSuite::get_key_account(&system_program::ID, 0);
+        ts.config.fee_bps = 100;
+        ts.config.fee_destination = Pubkey::new_unique();
+        ts.process_init_topic("test_topic", "test_option", vec![])
+            .unwrap();
+        ts.process_finish().unwrap();
+        let i = close_topic(
+            &ts.program_id,
+            &ts.topic_key.0,
+            &ts.topic_owner.0,
+            &refund_acc.0,
+            &fee_acc.0,
+        )
+        .unwrap();
+        assert_eq!(
+            Err(ProgramError::InvalidArgument),
+            do_process_instruction(
+                i,
+                vec![
+                    &mut ts.topic_key.1,
+                    &mut ts.topic_owner.1,
+                    &mut refund_acc.1,
+                    &mut fee_acc.1,
+                ],
+            )
+        );
+    }
+
+    #[test]
+    fn test_recompute_cache() {
+        let mut ts = TestSuite::new();
+        ts.process_init_topic("test_topic", "test_option", vec![])
+            .unwrap();
+        let mut key_acc = TestSuite::get_key_account(&system_program::ID, 100);
+        ts.process_vote(0, &mut key_acc).unwrap();
+        ts.process_anon_vote(0, [1; 32]).unwrap();
+
+        let mut topic = Topic::unpack_from_slice(&ts.topic_key.1.data).unwrap();
+        assert_eq!(2, topic.cached_total_votes);
+        topic.cached_total_votes = 7;
+        topic.pack_into_slice(&mut ts.topic_key.1.data).unwrap();
+
+        let i = recompute_cache(&ts.program_id, &ts.topic_key.0).unwrap();
+        do_process_instruction(i, vec![&mut ts.topic_key.1]).unwrap();
+        let topic = Topic::unpack_from_slice(&ts.topic_key.1.data).unwrap();
+        assert_eq!(2, topic.cached_total_votes);
+    }
+
+    #[test]
+    fn test_paused_topic() {
+        let mut ts = TestSuite::new();
+        ts.process_init_topic("test_topic", "test_option", vec![])
+            .unwrap();
+        ts.process_set_paused(true).unwrap();
+        let mut key_acc = TestSuite::get_key_account(&system_program::ID, 100);
+        assert_eq!(
+            Err(TopicError::TopicPaused.into()),
+            ts.process_vote(0, &mut key_acc)
+        );
+        assert_eq!(
+            Err(TopicError::TopicPaused.into()),
+            ts.process_anon_vote(0, [1; 32])
+        );
+        assert_eq!(
+            Err(ProgramError::InvalidAccountData),
+            ts.process_add_option("test_option2")
+        );
+
+        ts.process_set_paused(false).unwrap();
+        ts.process_vote(0, &mut key_acc).unwrap();
+        ts.process_set_paused(true).unwrap();
+        ts.process_finish().unwrap();
+        let topic = Topic::unpack_from_slice(&ts.topic_key.1.data).unwrap();
+        assert_eq!(TopicState::Finished, topic.state());
+    }
+
+    #[test]
+    fn test_token_vote_shared_token_account() {
+        let mut ts = TestSuite::new();
+        ts.process_init_topic("test_topic", "test_option", vec!["test_option2"])
+            .unwrap();
+        let mut wallet_a = TestSuite::get_key_account(&system_program::ID, 0);
+        let mut wallet_b = TestSuite::get_key_account(&system_program::ID, 0);
+        let mut token_acc = get_token_account(&ts.config.token_mint, &wallet_a.0, 1);
+
+        let i = token_vote(
+            &ts.program_id,
+            &ts.topic_key.0,
+            &wallet_a.0,
+            &token_acc.0,
+            OptIdx(0),
+        )
+        .unwrap();
+        do_process_instruction(
+            i,
+            vec![&mut ts.topic_key.1, &mut wallet_a.1, &mut token_acc.1],
+        )
+        .unwrap();
+
+        // the account moves to another wallet, which cannot vote with it again
+        token_acc.1.data[32..64].copy_from_slice(wallet_b.0.as_ref());
+        let i = token_vote(
+            &ts.program_id,
+            &ts.topic_key.0,
+            &wallet_b.0,
+            &token_acc.0,
+            OptIdx(1),
+        )
+        .unwrap();
+        assert_eq!(
+            Err(TopicError::TokenAccountUsed.into()),
+            do_process_instruction(
+                i,
+                vec![&mut ts.topic_key.1, &mut wallet_b.1, &mut token_acc.1]
+            )
+        );
+        let topic = Topic::unpack_from_slice(&ts.topic_key.1.data).unwrap();
+        assert_eq!(1, topic.total_votes());
+        assert_eq!(&[token_acc.0], topic.options[0].active_token_accounts());
+    }
+
+    #[test]
+    fn test_whitelist() {
+        let mut ts = TestSuite::new();
+        ts.process_init_topic("test_topic", "test_option", vec![])
+            .unwrap();
+        let mut listed = TestSuite::get_key_account(&system_program::ID, 100);
+        let mut unlisted = TestSuite::get_key_account(&system_program::ID, 100);
+        ts.process_add_voter(&listed.0, 1).unwrap();
+
+        assert_eq!(
+            Err(TopicError::VoterNotWhitelisted.into()),
+            ts.process_vote(0, &mut unlisted)
+        );
+        ts.process_vote(0, &mut listed).unwrap();
+        let topic = Topic::unpack_from_slice(&ts.topic_key.1.data).unwrap();
+        assert_eq!(1, topic.eligible_voter_count());
+        assert_eq!(1, topic.unique_voter_count());
+    }
+
+    #[test]
+    fn test_unknown_instruction_tag() {
+        let mut ts = TestSuite::new();
+        take_logs();
+        let instruction = Instruction {
+            program_id: ts.program_id,
+            accounts: vec![AccountMeta::new(ts.topic_key.0, false)],
+            data: vec![99],
+        };
+        assert_eq!(
+            Err(TopicError::UnknownInstruction.into()),
+            do_process_instruction(instruction, vec![&mut ts.topic_key.1])
+        );
+        assert!(take_logs()
+            .iter()
+            .any(|l| l == "unknown instruction tag 99"));
+    }
+
+    #[test]
+    fn test_cosigned_vote() {
+        let mut ts = TestSuite::new();
+        let mut cosigner = TestSuite::get_key_account(&system_program::ID, 0);
+        ts.config.require_cosigner = true;
+        ts.config.cosigner = cosigner.0;
+        ts.process_init_topic("test_topic", "test_option", vec![])
+            .unwrap();
+        let mut voter = TestSuite::get_key_account(&system_program::ID, 0);
+
+        assert_eq!(
+            Err(ProgramError::NotEnoughAccountKeys),
+            ts.process_vote(0, &mut voter)
+        );
+
+        let mut i = cosigned_vote_topic(
+            &ts.program_id,
+            &ts.topic_key.0,
+            &voter.0,
+            &cosigner.0,
+            OptIdx(0),
+        )
+        .unwrap();
+        i.accounts[2].is_signer = false;
+        assert_eq!(
+            Err(ProgramError::MissingRequiredSignature),
+            do_process_instruction(i, vec![&mut ts.topic_key.1, &mut voter.1, &mut cosigner.1])
+        );
 
+        let mut impostor = TestSuite::get_key_account(&system_program::ID, 0);
+        let i = cosigned_vote_topic(
+            &ts.program_id,
+            &ts.topic_key.0,
+            &voter.0,
+            &impostor.0,
+            OptIdx(0),
+        )
+        .unwrap();
         assert_eq!(
-            Err(ProgramError::AccountAlreadyInitialized),
-            ts.process_create_topic("test_topic_2", "test_option")
+            Err(ProgramError::MissingRequiredSignature),
+            do_process_instruction(i, vec![&mut ts.topic_key.1, &mut voter.1, &mut impostor.1])
+        );
+        let topic = Topic::unpack_from_slice(&ts.topic_key.1.data).unwrap();
+        assert_eq!(0, topic.total_votes());
+
+        let i = cosigned_vote_topic(
+            &ts.program_id,
+            &ts.topic_key.0,
+            &voter.0,
+            &cosigner.0,
+            OptIdx(0),
         )
+        .unwrap();
+        do_process_instruction(i, vec![&mut ts.topic_key.1, &mut voter.1, &mut cosigner.1])
+            .unwrap();
+        let topic = Topic::unpack_from_slice(&ts.topic_key.1.data).unwrap();
+        assert_eq!(1, topic.total_votes());
     }
 
     #[test]
-    fn test_add_option() {
+    fn test_cosigner_required_without_key() {
         let mut ts = TestSuite::new();
-        let topic_name = "test_topic";
-        let opt_name = "test_option";
-        ts.process_create_topic(topic_name, opt_name).unwrap();
-        let mut expect_topic = Topic::new(topic_name, &ts.topic_owner.0);
-        expect_topic.add_option(&ts.topic_key.0, opt_name).unwrap();
-        let opt_name = "test_option2";
-        ts.process_add_option(opt_name).unwrap();
-        expect_topic.add_option(&ts.topic_key.0, opt_name).unwrap();
+        ts.config.require_cosigner = true;
+        assert_eq!(
+            Err(ProgramError::InvalidArgument),
+            ts.process_create_topic("test_topic", "test_option")
+        );
+    }
 
-        assert_eq!(Ok(true), ts.topic_eq(&expect_topic))
+    #[test]
+    fn test_vote_nonce() {
+        let mut ts = TestSuite::new();
+        ts.process_init_topic("test_topic", "test_option", vec![])
+            .unwrap();
+        let mut voter = TestSuite::get_key_account(&system_program::ID, 0);
+
+        let i = vote_topic_with_nonce(
+            &ts.program_id,
+            &ts.topic_key.0,
+            &voter.0,
+            OptIdx(0),
+            Some(42),
+        )
+        .unwrap();
+        do_process_instruction(i.clone(), vec![&mut ts.topic_key.1, &mut voter.1]).unwrap();
+        let topic = Topic::unpack_from_slice(&ts.topic_key.1.data).unwrap();
+        assert_eq!(1, topic.total_votes());
+
+        assert_eq!(
+            Err(TopicError::NonceReused.into()),
+            do_process_instruction(i, vec![&mut ts.topic_key.1, &mut voter.1])
+        );
+        let topic = Topic::unpack_from_slice(&ts.topic_key.1.data).unwrap();
+        assert_eq!(1, topic.total_votes());
     }
 
     #[test]
-    fn test_vote() {
+    fn test_vote_twice() {
         let mut ts = TestSuite::new();
-        let topic_name = "test_topic";
-        let opt_name = "test_option";
-        let opt_name2 = "test_option2";
-        ts.process_init_topic(topic_name, opt_name, vec![opt_name2])
+        ts.process_init_topic("test_topic", "test_option", vec!["test_option2"])
             .unwrap();
-        let mut expect_topic = Topic::new(topic_name, &ts.topic_owner.0);
-        expect_topic.add_option(&ts.topic_key.0, opt_name).unwrap();
-        expect_topic.add_option(&ts.topic_key.0, opt_name2).unwrap();
+        let mut key_acc = TestSuite::get_key_account(&system_program::ID, 100);
+        ts.process_vote(0, &mut key_acc).unwrap();
+        assert_eq!(
+            Err(TopicError::AlreadyVoted.into()),
+            ts.process_vote(1, &mut key_acc)
+        );
+    }
 
+    #[test]
+    fn test_vote_after_deadline() {
+        let mut ts = TestSuite::new();
+        ts.config.deadline = 1_000;
+        ts.process_init_topic("test_topic", "test_option", vec![])
+            .unwrap();
         let mut key_acc = TestSuite::get_key_account(&system_program::ID, 100);
+        set_clock(1_001);
+        assert_eq!(
+            Err(TopicError::DeadlinePassed.into()),
+            ts.process_vote(0, &mut key_acc)
+        );
+        set_clock(1_000);
         ts.process_vote(0, &mut key_acc).unwrap();
-        expect_topic.options[0].add_voter(&key_acc.0).unwrap();
-        assert_eq!(Ok(true), ts.topic_eq(&expect_topic))
     }
 
     #[test]
-    fn test_finish_topic() {
+    fn test_quadratic_vote() {
         let mut ts = TestSuite::new();
-        let topic_name = "test_topic";
-        let opt_name = "test_option";
-        let opt_name2 = "test_option2";
-        ts.process_init_topic(topic_name, opt_name, vec![opt_name2])
+        ts.config.tally_mode = TallyMode::Quadratic;
+        ts.process_init_topic("test_topic", "test_option", vec!["test_option2"])
             .unwrap();
-        let mut expect_topic = Topic::new(topic_name, &ts.topic_owner.0);
-        expect_topic.add_option(&ts.topic_key.0, opt_name).unwrap();
-        expect_topic.add_option(&ts.topic_key.0, opt_name2).unwrap();
+        let vote = |ts: &mut TestSuite, opt_idx: u8, amount: u64| {
+            let mut voter = TestSuite::get_key_account(&system_program::ID, 0);
+            let i = quadratic_vote(
+                &ts.program_id,
+                &ts.topic_key.0,
+                &voter.0,
+                OptIdx(opt_idx),
+                amount,
+            )
+            .unwrap();
+            do_process_instruction(i, vec![&mut ts.topic_key.1, &mut voter.1])
+        };
+        vote(&mut ts, 0, 100).unwrap();
+        for _ in 0..3 {
+            vote(&mut ts, 1, 16).unwrap();
+        }
+        assert_eq!(
+            Err(TopicError::VoterBalanceTooLow.into()),
+            vote(&mut ts, 0, 10_001)
+        );
+        let mut voter = TestSuite::get_key_account(&system_program::ID, 0);
+        assert_eq!(
+            Err(ProgramError::InvalidInstructionData),
+            ts.process_vote(0, &mut voter)
+        );
+
         ts.process_finish().unwrap();
-        expect_topic.is_finished = true;
-        expect_topic.result_idx = 1;
-        assert_eq!(Ok(true), ts.topic_eq(&expect_topic))
+        let topic = Topic::unpack_from_slice(&ts.topic_key.1.data).unwrap();
+        assert_eq!(10, topic.option_score(&topic.options[0]));
+        assert_eq!(12, topic.option_score(&topic.options[1]));
+        assert_eq!(1, topic.result_idx);
+    }
+
+    #[test]
+    fn test_vote_finished_topic() {
+        let mut ts = TestSuite::new();
+        ts.process_init_topic("test_topic", "test_option", vec![])
+            .unwrap();
+        ts.process_finish().unwrap();
+        let mut key_acc = TestSuite::get_key_account(&system_program::ID, 100);
+        assert_eq!(
+            Err(TopicError::AlreadyFinished.into()),
+            ts.process_vote(0, &mut key_acc)
+        );
+    }
+
+    #[test]
+    fn test_whitelist_weighted_vote() {
+        let mut ts = TestSuite::new();
+        ts.config.tally_mode = TallyMode::WhitelistWeight;
+        ts.process_init_topic("test_topic", "test_option", vec!["test_option2"])
+            .unwrap();
+        let mut voters: Vec<_> = (0..3)
+            .map(|_| TestSuite::get_key_account(&system_program::ID, 100))
+            .collect();
+        ts.process_add_voter(&voters[0].0, 5).unwrap();
+        ts.process_add_voter(&voters[1].0, 2).unwrap();
+        ts.process_add_voter(&voters[2].0, 2).unwrap();
+
+        ts.process_vote(1, &mut voters[0]).unwrap();
+        ts.process_vote(0, &mut voters[1]).unwrap();
+        ts.process_vote(0, &mut voters[2]).unwrap();
+        ts.process_finish().unwrap();
+
+        let topic = Topic::unpack_from_slice(&ts.topic_key.1.data).unwrap();
+        assert_eq!(2, topic.options[0].vote_count());
+        assert_eq!(4, topic.option_score(&topic.options[0]));
+        assert_eq!(5, topic.option_score(&topic.options[1]));
+        assert_eq!(1, topic.result_idx);
+    }
+
+    #[test]
+    fn test_finish_return_data() {
+        let mut ts = TestSuite::new();
+        ts.process_init_topic("test_topic", "test_option", vec!["test_option2"])
+            .unwrap();
+        for opt_idx in [1, 0, 1] {
+            let mut key_acc = TestSuite::get_key_account(&system_program::ID, 100);
+            ts.process_vote(opt_idx, &mut key_acc).unwrap();
+        }
+        ts.process_finish().unwrap();
+
+        let topic = Topic::unpack_from_slice(&ts.topic_key.1.data).unwrap();
+        let data = return_data();
+        assert_eq!(9, data.len());
+        assert_eq!(topic.result_idx, data[0]);
+        assert_eq!(1, data[0]);
+        assert_eq!(2, unpack_u64(array_ref![data, 1, 8]));
+
+        // a quadratic topic is won by weight: one vote of 10 beats two votes of 1
+        let mut ts = TestSuite::new();
+        ts.config.tally_mode = TallyMode::Quadratic;
+        ts.process_init_topic("test_topic", "test_option", vec!["test_option2"])
+            .unwrap();
+        for (opt_idx, amount) in [(0, 100), (1, 1), (1, 1)] {
+            let mut voter = TestSuite::get_key_account(&system_program::ID, 0);
+            let i = quadratic_vote(
+                &ts.program_id,
+                &ts.topic_key.0,
+                &voter.0,
+                OptIdx(opt_idx),
+                amount,
+            )
+            .unwrap();
+            do_process_instruction(i, vec![&mut ts.topic_key.1, &mut voter.1]).unwrap();
+        }
+        ts.process_finish().unwrap();
+        let data = return_data();
+        assert_eq!(0, data[0]);
+        assert_eq!(10, unpack_u64(array_ref![data, 1, 8]));
+    }
+
+    #[test]
+    fn test_auto_finish_on_quorum() {
+        let mut ts = TestSuite::new();
+        ts.config.auto_finish_on_quorum = true;
+        assert_eq!(
+            Err(ProgramError::InvalidArgument),
+            ts.process_create_topic("test_topic", "test_option")
+        );
+
+        ts.config.min_quorum = 2;
+        ts.process_init_topic("test_topic", "test_option", vec!["test_option2"])
+            .unwrap();
+        let mut voters: Vec<_> = (0..3)
+            .map(|_| TestSuite::get_key_account(&system_program::ID, 100))
+            .collect();
+        ts.process_vote(1, &mut voters[0]).unwrap();
+        let topic = Topic::unpack_from_slice(&ts.topic_key.1.data).unwrap();
+        assert_eq!(TopicState::Open, topic.state());
+
+        ts.process_vote(1, &mut voters[1]).unwrap();
+        let topic = Topic::unpack_from_slice(&ts.topic_key.1.data).unwrap();
+        assert_eq!(TopicState::Finished, topic.state());
+        assert_eq!(1, topic.result_idx);
+        assert_eq!(
+            Err(TopicError::AlreadyFinished.into()),
+            ts.process_vote(0, &mut voters[2])
+        );
+    }
+
+    #[test]
+    fn test_finish_account_count() {
+        let mut ts = TestSuite::new();
+        ts.process_init_topic("test_topic", "test_option", vec![])
+            .unwrap();
+        let finish = |ts: &TestSuite| {
+            finish_topic(&ts.program_id, &ts.topic_key.0, &ts.topic_owner.0).unwrap()
+        };
+
+        let mut i = finish(&ts);
+        i.accounts.truncate(1);
+        assert_eq!(
+            Err(ProgramError::NotEnoughAccountKeys),
+            do_process_instruction(i, vec![&mut ts.topic_key.1])
+        );
+
+        let mut i = finish(&ts);
+        let mut extra = TestSuite::get_key_account(&system_program::ID, 100);
+        i.accounts.push(AccountMeta::new(extra.0, false));
+        assert_eq!(
+            Err(TopicError::UnexpectedAccounts.into()),
+            do_process_instruction(
+                i,
+                vec![&mut ts.topic_key.1, &mut ts.topic_owner.1, &mut extra.1]
+            )
+        );
+        let topic = Topic::unpack_from_slice(&ts.topic_key.1.data).unwrap();
+        assert!(!topic.is_finished);
+
+        let i = finish(&ts);
+        do_process_instruction(i, vec![&mut ts.topic_key.1, &mut ts.topic_owner.1]).unwrap();
+        let topic = Topic::unpack_from_slice(&ts.topic_key.1.data).unwrap();
+        assert!(topic.is_finished);
+    }
+
+    #[test]
+    fn test_get_my_vote() {
+        let mut ts = TestSuite::new();
+        ts.process_init_topic("test_topic", "test_option", vec!["test_option2"])
+            .unwrap();
+        let mut voter = TestSuite::get_key_account(&system_program::ID, 100);
+        let mut bystander = TestSuite::get_key_account(&system_program::ID, 100);
+        ts.process_vote(1, &mut voter).unwrap();
+        let before = ts.topic_key.1.data.clone();
+
+        for (acc, expect) in [(&mut voter, "MYVOTE=1"), (&mut bystander, "MYVOTE=none")] {
+            take_logs();
+            let i = get_my_vote(&ts.program_id, &ts.topic_key.0, &acc.0).unwrap();
+            do_process_instruction(i, vec![&mut ts.topic_key.1, &mut acc.1]).unwrap();
+            assert!(take_logs().iter().any(|l| l == expect));
+        }
+        assert_eq!(before, ts.topic_key.1.data);
+    }
+
+    #[test]
+    fn test_set_quorum() {
+        let mut ts = TestSuite::new();
+        ts.config.min_quorum = 3;
+        ts.process_init_topic("test_topic", "test_option", vec![])
+            .unwrap();
+        ts.process_set_quorum(5).unwrap();
+        for _ in 0..2 {
+            let mut key_acc = TestSuite::get_key_account(&system_program::ID, 100);
+            ts.process_vote(0, &mut key_acc).unwrap();
+        }
+        assert_eq!(Err(ProgramError::InvalidArgument), ts.process_set_quorum(2));
+        ts.process_set_quorum(1).unwrap();
+        let topic = Topic::unpack_from_slice(&ts.topic_key.1.data).unwrap();
+        assert_eq!(1, topic.config.min_quorum);
+
+        let mut i = set_quorum(&ts.program_id, &ts.topic_key.0, &ts.topic_owner.0, 4).unwrap();
+        i.accounts[1].is_signer = false;
+        assert_eq!(
+            Err(ProgramError::MissingRequiredSignature),
+            do_process_instruction(i, vec![&mut ts.topic_key.1, &mut ts.topic_owner.1])
+        );
+        let mut stranger = TestSuite::get_key_account(&system_program::ID, 100);
+        let i = set_quorum(&ts.program_id, &ts.topic_key.0, &stranger.0, 4).unwrap();
+        assert_eq!(
+            Err(TopicError::NotTopicOwner.into()),
+            do_process_instruction(i, vec![&mut ts.topic_key.1, &mut stranger.1])
+        );
+
+        ts.process_finish().unwrap();
+        assert_eq!(
+            Err(ProgramError::InvalidAccountData),
+            ts.process_set_quorum(4)
+        );
+    }
+
+    #[test]
+    fn test_owner_errors_are_distinct() {
+        let mut ts = TestSuite::new();
+        ts.process_init_topic("test_topic", "test_option", vec![])
+            .unwrap();
+        let mut stranger = TestSuite::get_key_account(&system_program::ID, 100);
+        let i = finish_topic(&ts.program_id, &ts.topic_key.0, &stranger.0).unwrap();
+        assert_eq!(
+            Err(TopicError::NotTopicOwner.into()),
+            do_process_instruction(i, vec![&mut ts.topic_key.1, &mut stranger.1])
+        );
+
+        ts.topic_key.1.owner = Pubkey::new_unique();
+        assert_eq!(
+            Err(TopicError::WrongProgramOwner.into()),
+            ts.process_finish()
+        );
+        assert_ne!(
+            ProgramError::from(TopicError::NotTopicOwner),
+            ProgramError::from(TopicError::WrongProgramOwner)
+        );
+    }
+
+    #[test]
+    fn test_min_action_interval() {
+        let mut ts = TestSuite::new();
+        ts.config.min_action_interval = 10;
+        set_clock(100);
+        ts.process_init_topic("test_topic", "test_option", vec![])
+            .unwrap();
+        set_clock(105);
+        assert_eq!(
+            Err(TopicError::TooSoon.into()),
+            ts.process_add_option("test_option2")
+        );
+        set_clock(110);
+        ts.process_add_option("test_option2").unwrap();
+
+        let mut key_acc = TestSuite::get_key_account(&system_program::ID, 100);
+        set_clock(119);
+        assert_eq!(
+            Err(TopicError::TooSoon.into()),
+            ts.process_vote(0, &mut key_acc)
+        );
+        set_clock(120);
+        ts.process_vote(0, &mut key_acc).unwrap();
+        let topic = Topic::unpack_from_slice(&ts.topic_key.1.data).unwrap();
+        assert_eq!(120, topic.last_action_at);
+        set_clock(0);
+    }
+
+    #[test]
+    fn test_create_topic_over_stale_data() {
+        let mut ts = TestSuite::new();
+        let pk = Pubkey::new_unique();
+        let mut stale = Topic::new_with_options("old_topic", &pk, &pk, &["a", "b"]).unwrap();
+        stale.vote(OptIdx(1), &pk, 7).unwrap();
+        stale.ban_voter(&pk).unwrap();
+        stale.pack_into_slice(&mut ts.topic_key.1.data).unwrap();
+        ts.topic_key.1.data[..100].fill(0);
+
+        ts.process_create_topic("test_topic", "test_option")
+            .unwrap();
+        let expect = ts.expected_topic("test_topic", &["test_option"]);
+        assert_eq!(Ok(true), ts.topic_eq(&expect));
+    }
+
+    fn get_token_account(mint: &Pubkey, owner: &Pubkey, amount: u64) -> (Pubkey, SolanaAccount) {
+        let mut acc = TestSuite::get_key_account(&TOKEN_PROGRAM_ID, 165);
+        acc.1.data[..32].copy_from_slice(mint.as_ref());
+        acc.1.data[32..64].copy_from_slice(owner.as_ref());
+        acc.1.data[64..72].copy_from_slice(&amount.to_le_bytes());
+        acc
+    }
+
+    #[test]
+    fn test_token_vote_checks_token_account() {
+        let mut ts = TestSuite::new();
+        ts.config.token_mint = Pubkey::new_unique();
+        ts.process_init_topic("test_topic", "test_option", vec![])
+            .unwrap();
+        let mut voter = TestSuite::get_key_account(&system_program::ID, 0);
+        let other = Pubkey::new_unique();
+
+        for (mut token_acc, err) in [
+            (
+                get_token_account(&ts.config.token_mint, &other, 1),
+                ProgramError::IllegalOwner,
+            ),
+            (
+                get_token_account(&Pubkey::new_unique(), &voter.0, 1),
+                TopicError::TokenMintMismatch.into(),
+            ),
+            (
+                TestSuite::get_key_account(&system_program::ID, 165),
+                ProgramError::IncorrectProgramId,
+            ),
+        ] {
+            let i = token_vote(
+                &ts.program_id,
+                &ts.topic_key.0,
+                &voter.0,
+                &token_acc.0,
+                OptIdx(0),
+            )
+            .unwrap();
+            assert_eq!(
+                Err(err),
+                do_process_instruction(
+                    i,
+                    vec![&mut ts.topic_key.1, &mut voter.1, &mut token_acc.1]
+                )
+            );
+        }
+        let topic = Topic::unpack_from_slice(&ts.topic_key.1.data).unwrap();
+        assert_eq!(0, topic.total_votes());
+
+        let mut token_acc = get_token_account(&ts.config.token_mint, &voter.0, 1);
+        let i = token_vote(
+            &ts.program_id,
+            &ts.topic_key.0,
+            &voter.0,
+            &token_acc.0,
+            OptIdx(0),
+        )
+        .unwrap();
+        do_process_instruction(i, vec![&mut ts.topic_key.1, &mut voter.1, &mut token_acc.1])
+            .unwrap();
+    }
+
+    #[test]
+    fn test_vote_multi_source() {
+        let mut ts = TestSuite::new();
+        ts.config.tally_mode = TallyMode::TokenWeight;
+        ts.process_init_topic("test_topic", "test_option", vec!["test_option2"])
+            .unwrap();
+        let mut voter = TestSuite::get_key_account(&system_program::ID, 100);
+        let mint = Pubkey::new_unique();
+        let mut token_a = get_token_account(&mint, &voter.0, 30);
+        let mut token_b = get_token_account(&mint, &voter.0, 12);
+        let mut other_mint = get_token_account(&Pubkey::new_unique(), &voter.0, 5);
+
+        let i = vote_multi_source(
+            &ts.program_id,
+            &ts.topic_key.0,
+            &voter.0,
+            &[token_a.0, other_mint.0],
+            OptIdx(1),
+        )
+        .unwrap();
+        assert_eq!(
+            Err(TopicError::TokenMintMismatch.into()),
+            do_process_instruction(
+                i,
+                vec![
+                    &mut ts.topic_key.1,
+                    &mut voter.1,
+                    &mut token_a.1,
+                    &mut other_mint.1
+                ]
+            )
+        );
+
+        let i = vote_multi_source(
+            &ts.program_id,
+            &ts.topic_key.0,
+            &voter.0,
+            &[token_a.0, token_b.0],
+            OptIdx(1),
+        )
+        .unwrap();
+        do_process_instruction(
+            i.clone(),
+            vec![
+                &mut ts.topic_key.1,
+                &mut voter.1,
+                &mut token_a.1,
+                &mut token_b.1,
+            ],
+        )
+        .unwrap();
+        assert_eq!(vec![1, 1, 0, 0, 0], return_data());
+        let topic = Topic::unpack_from_slice(&ts.topic_key.1.data).unwrap();
+        assert_eq!(42, topic.option_score(&topic.options[1]));
+        assert_eq!(1, topic.total_votes());
+
+        assert_eq!(
+            Err(TopicError::AlreadyVoted.into()),
+            do_process_instruction(
+                i,
+                vec![
+                    &mut ts.topic_key.1,
+                    &mut voter.1,
+                    &mut token_a.1,
+                    &mut token_b.1,
+                ],
+            )
+        );
+        let mut other_voter = TestSuite::get_key_account(&system_program::ID, 100);
+        assert_eq!(
+            Err(ProgramError::InvalidInstructionData),
+            ts.process_vote(0, &mut other_voter)
+        );
+    }
+
+    #[test]
+    fn test_vote_multi_source_rejects_foreign_accounts() {
+        let mut ts = TestSuite::new();
+        ts.config.tally_mode = TallyMode::TokenWeight;
+        ts.process_init_topic("test_topic", "test_option", vec![])
+            .unwrap();
+        let mut voter = TestSuite::get_key_account(&system_program::ID, 100);
+        let mint = Pubkey::new_unique();
+        let mut forged = get_token_account(&mint, &voter.0, 1_000);
+        forged.1.owner = system_program::ID;
+        let mut not_held = get_token_account(&mint, &Pubkey::new_unique(), 1_000);
+
+        for (token, err) in [
+            (&mut forged, ProgramError::IncorrectProgramId),
+            (&mut not_held, ProgramError::IllegalOwner),
+        ] {
+            let i = vote_multi_source(
+                &ts.program_id,
+                &ts.topic_key.0,
+                &voter.0,
+                &[token.0],
+                OptIdx(0),
+            )
+            .unwrap();
+            assert_eq!(
+                Err(err),
+                do_process_instruction(i, vec![&mut ts.topic_key.1, &mut voter.1, &mut token.1])
+            );
+        }
+    }
+
+    #[test]
+    fn test_retract_and_change_vote() {
+        let mut ts = TestSuite::new();
+        ts.process_init_topic("test_topic", "test_option", vec!["test_option2"])
+            .unwrap();
+        let mut voter = TestSuite::get_key_account(&system_program::ID, 100);
+        ts.process_vote(0, &mut voter).unwrap();
+
+        let i = change_vote(&ts.program_id, &ts.topic_key.0, &voter.0, OptIdx(1)).unwrap();
+        do_process_instruction(i, vec![&mut ts.topic_key.1, &mut voter.1]).unwrap();
+        assert_eq!(vec![1, 1, 0, 0, 0], return_data());
+        let topic = Topic::unpack_from_slice(&ts.topic_key.1.data).unwrap();
+        assert_eq!(Some(1), topic.voted_option(&voter.0));
+        assert_eq!(1, topic.total_votes());
+
+        let i = retract_vote(&ts.program_id, &ts.topic_key.0, &voter.0).unwrap();
+        do_process_instruction(i.clone(), vec![&mut ts.topic_key.1, &mut voter.1]).unwrap();
+        let topic = Topic::unpack_from_slice(&ts.topic_key.1.data).unwrap();
+        assert_eq!(None, topic.voted_option(&voter.0));
+        assert_eq!(0, topic.total_votes());
+        assert_eq!(
+            Err(ProgramError::InvalidArgument),
+            do_process_instruction(i, vec![&mut ts.topic_key.1, &mut voter.1])
+        );
+        ts.process_vote(0, &mut voter).unwrap();
+    }
+
+    #[test]
+    fn test_vote_changes_disabled() {
+        let mut ts = TestSuite::new();
+        ts.config.allow_vote_changes = false;
+        ts.process_init_topic("test_topic", "test_option", vec!["test_option2"])
+            .unwrap();
+        let mut voter = TestSuite::get_key_account(&system_program::ID, 100);
+        ts.process_vote(0, &mut voter).unwrap();
+
+        let i = change_vote(&ts.program_id, &ts.topic_key.0, &voter.0, OptIdx(1)).unwrap();
+        assert_eq!(
+            Err(TopicError::VoteChangesDisabled.into()),
+            do_process_instruction(i, vec![&mut ts.topic_key.1, &mut voter.1])
+        );
+        let mut i = retract_vote(&ts.program_id, &ts.topic_key.0, &voter.0).unwrap();
+        assert_eq!(
+            Err(TopicError::VoteChangesDisabled.into()),
+            do_process_instruction(i.clone(), vec![&mut ts.topic_key.1, &mut voter.1])
+        );
+        i.accounts[1].is_signer = false;
+        assert_eq!(
+            Err(ProgramError::MissingRequiredSignature),
+            do_process_instruction(i, vec![&mut ts.topic_key.1, &mut voter.1])
+        );
+        let topic = Topic::unpack_from_slice(&ts.topic_key.1.data).unwrap();
+        assert_eq!(Some(0), topic.voted_option(&voter.0));
+    }
+
+    fn get_oracle_account(
+        authority: &Pubkey,
+        voter: &Pubkey,
+        weight: u64,
+    ) -> (Pubkey, SolanaAccount) {
+        let mut acc = TestSuite::get_key_account(authority, 40);
+        acc.1.data[..32].copy_from_slice(voter.as_ref());
+        acc.1.data[32..].copy_from_slice(&weight.to_le_bytes());
+        acc
+    }
+
+    #[test]
+    fn test_oracle_weighted_vote() {
+        let mut ts = TestSuite::new();
+        let authority = Pubkey::new_unique();
+        ts.config.oracle_weighted = true;
+        ts.config.oracle_authority = authority;
+        ts.process_init_topic("test_topic", "test_option", vec!["test_option2"])
+            .unwrap();
+        let mut voter = TestSuite::get_key_account(&system_program::ID, 100);
+        let mut oracle = get_oracle_account(&authority, &voter.0, 25);
+
+        let i = oracle_vote_topic(
+            &ts.program_id,
+            &ts.topic_key.0,
+            &voter.0,
+            &oracle.0,
+            OptIdx(1),
+        )
+        .unwrap();
+        do_process_instruction(i, vec![&mut ts.topic_key.1, &mut voter.1, &mut oracle.1]).unwrap();
+        let topic = Topic::unpack_from_slice(&ts.topic_key.1.data).unwrap();
+        assert_eq!(25, topic.option_score(&topic.options[1]));
+        assert_eq!(1, topic.total_votes());
+
+        let mut other_voter = TestSuite::get_key_account(&system_program::ID, 100);
+        assert_eq!(
+            Err(ProgramError::NotEnoughAccountKeys),
+            ts.process_vote(0, &mut other_voter)
+        );
+    }
+
+    #[test]
+    fn test_oracle_weighted_vote_spoofed_oracle() {
+        let mut ts = TestSuite::new();
+        let authority = Pubkey::new_unique();
+        ts.config.oracle_weighted = true;
+        ts.config.oracle_authority = authority;
+        ts.process_init_topic("test_topic", "test_option", vec![])
+            .unwrap();
+        let mut voter = TestSuite::get_key_account(&system_program::ID, 100);
+        let mut spoofed = get_oracle_account(&Pubkey::new_unique(), &voter.0, 1_000);
+        let mut for_other_voter = get_oracle_account(&authority, &Pubkey::new_unique(), 1_000);
+
+        for oracle in [&mut spoofed, &mut for_other_voter] {
+            let i = oracle_vote_topic(
+                &ts.program_id,
+                &ts.topic_key.0,
+                &voter.0,
+                &oracle.0,
+                OptIdx(0),
+            )
+            .unwrap();
+            assert_eq!(
+                Err(TopicError::OracleMismatch.into()),
+                do_process_instruction(i, vec![&mut ts.topic_key.1, &mut voter.1, &mut oracle.1])
+            );
+        }
+        let topic = Topic::unpack_from_slice(&ts.topic_key.1.data).unwrap();
+        assert_eq!(0, topic.total_votes());
+    }
+
+    #[test]
+    fn test_vote_blocked_while_draft() {
+        let mut ts = TestSuite::new();
+        ts.config.min_options = 2;
+        ts.process_create_topic("test_topic", "test_option")
+            .unwrap();
+        let topic = Topic::unpack_from_slice(&ts.topic_key.1.data).unwrap();
+        assert!(topic.is_draft());
+        let mut key_acc = TestSuite::get_key_account(&system_program::ID, 100);
+        assert_eq!(
+            Err(ProgramError::InvalidAccountData),
+            ts.process_vote(0, &mut key_acc)
+        );
+        assert_eq!(Err(ProgramError::InvalidAccountData), ts.process_finish());
+
+        ts.process_add_option("test_option2").unwrap();
+        let topic = Topic::unpack_from_slice(&ts.topic_key.1.data).unwrap();
+        assert_eq!(TopicState::Open, topic.state());
+        ts.process_vote(0, &mut key_acc).unwrap();
+    }
+
+    #[test]
+    fn test_admin_reset() {
+        let mut ts = TestSuite::new();
+        ts.process_init_topic("test_topic", "test_option", vec!["test_option2"])
+            .unwrap();
+        // An option count past MAX_OPTIONS makes the topic fail to unpack.
+        let mut corrupt = Topic::unpack_from_slice(&ts.topic_key.1.data).unwrap();
+        corrupt.opt_current_idx = MAX_OPTIONS as u8 + 1;
+        corrupt.pack_into_slice(&mut ts.topic_key.1.data).unwrap();
+        assert_eq!(
+            Err(ProgramError::InvalidAccountData),
+            Topic::unpack_from_slice(&ts.topic_key.1.data)
+        );
+
+        let mut stranger = TestSuite::get_key_account(&system_program::ID, 0);
+        let i = admin_reset(&ts.program_id, &ts.topic_key.0, &stranger.0).unwrap();
+        assert_eq!(
+            Err(TopicError::NotAdmin.into()),
+            do_process_instruction(i, vec![&mut ts.topic_key.1, &mut stranger.1])
+        );
+        // Builds without `XPROGRAM_ADMIN` have no admin, so nobody can reset.
+        let admin_key = match ADMIN {
+            Some(admin_key) => admin_key,
+            None => return,
+        };
+        let mut admin = (admin_key, SolanaAccount::new(0, 0, &system_program::ID));
+        let mut i = admin_reset(&ts.program_id, &ts.topic_key.0, &admin.0).unwrap();
+        i.accounts[1].is_signer = false;
+        assert_eq!(
+            Err(ProgramError::MissingRequiredSignature),
+            do_process_instruction(i, vec![&mut ts.topic_key.1, &mut admin.1])
+        );
+        assert_eq!(
+            Err(ProgramError::InvalidAccountData),
+            Topic::unpack_from_slice(&ts.topic_key.1.data)
+        );
+
+        let i = admin_reset(&ts.program_id, &ts.topic_key.0, &admin.0).unwrap();
+        do_process_instruction(i, vec![&mut ts.topic_key.1, &mut admin.1]).unwrap();
+        assert!(ts.topic_key.1.data.iter().all(|&b| b == 0));
+        let topic = Topic::unpack_from_slice(&ts.topic_key.1.data).unwrap();
+        assert_eq!(TopicState::Uninitialized, topic.state());
+        ts.process_create_topic("test_topic", "test_option")
+            .unwrap();
+    }
+
+    #[test]
+    fn test_create_topic_with_salt() {
+        let mut ts = TestSuite::new();
+        ts.config.salt = [9; 16];
+        ts.process_create_topic("test_topic", "test_option")
+            .unwrap();
+        let topic = Topic::unpack_from_slice(&ts.topic_key.1.data).unwrap();
+        assert_eq!([9; 16], topic.config.salt);
+
+        let nullifier = topic.nullifier(&[3; 32]);
+        ts.process_anon_vote(0, nullifier).unwrap();
+        let topic = Topic::unpack_from_slice(&ts.topic_key.1.data).unwrap();
+        assert!(topic.has_nullifier(&nullifier));
     }
 }
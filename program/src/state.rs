@@ -1,30 +1,140 @@
-use crate::util::{str_pack, str_unpack};
+use crate::{
+    error::TopicError,
+    util::{
+        isqrt, pack_i64, pack_u32, pack_u64, str_check, str_pack, str_unpack, str_unpack_lossy,
+        unpack_i64, unpack_u32, unpack_u64,
+    },
+};
 use arrayref::{array_mut_ref, array_ref, array_refs, mut_array_refs};
-use solana_program::{program_error::ProgramError, pubkey::Pubkey};
+use solana_program::{
+    account_info::AccountInfo,
+    hash::{hash, hashv},
+    program_error::ProgramError,
+    pubkey::Pubkey,
+};
+use std::convert::TryFrom;
 
-const MAX_TOPIC_NAME: usize = 100;
-const MAX_OPTION_NAME: usize = 100;
+pub const MAX_TOPIC_NAME: usize = 100;
+pub const MAX_OPTION_NAME: usize = 100;
+const MAX_NULLIFIERS: usize = 30;
+pub const MAX_OPTIONS: usize = 10;
+const MAX_VOTERS: usize = 30;
+const MAX_BANNED: usize = 10;
+const MAX_WHITELIST: usize = 30;
+const MAX_SEEN_NONCES: usize = 16;
+const OPTION_LEN: usize = 2303;
+const CONFIG_LEN: usize = 187;
+pub const NOTE_LEN: usize = 140;
+/// `eligible_voter_count` of a topic without a whitelist, where anyone may vote.
+pub const OPEN_VOTING: u32 = u32::MAX;
+const TOPIC_LEN: usize = 26664;
+
+/// Index of an option slot within a topic, kept apart from plain counts.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct OptIdx(pub u8);
+
+impl OptIdx {
+    /// Checks that `idx` points at an initialized option of `topic`.
+    pub fn new(idx: u8, topic: &Topic) -> Result<OptIdx, ProgramError> {
+        let opt_idx = OptIdx(idx);
+        if idx >= topic.opt_current_idx || topic.options[opt_idx.as_usize()].is_empty() {
+            return Err(ProgramError::InvalidArgument);
+        }
+        Ok(opt_idx)
+    }
+
+    pub fn as_usize(self) -> usize {
+        self.0 as usize
+    }
+}
+
+impl From<u8> for OptIdx {
+    fn from(idx: u8) -> Self {
+        OptIdx(idx)
+    }
+}
+
+impl From<OptIdx> for u8 {
+    fn from(idx: OptIdx) -> Self {
+        idx.0
+    }
+}
+
+/// Lifecycle stage of a topic, derived from its fields.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum TopicState {
+    /// The account has not been created as a topic yet.
+    Uninitialized,
+    /// Named, but still being set up: fewer options than `min_options` and no votes.
+    Draft,
+    Open,
+    /// Temporarily closed to votes and new options by the owner.
+    Paused,
+    Finished,
+}
 
 #[repr(C)]
 #[derive(Clone, Debug, PartialEq)]
 pub struct Topic {
     pub name: [u8; 100],
-    pub options: [Option; 10],
+    pub options: [Option; MAX_OPTIONS],
     pub opt_current_idx: u8,
     pub owner: Pubkey,
     pub result_idx: u8,
     pub is_finished: bool,
+    pub nullifiers: [[u8; 32]; MAX_NULLIFIERS],
+    pub nullifier_count: u8,
+    pub category: [u8; 32],
+    pub config: TopicConfig,
+    /// Free-form note the owner may attach once the topic is finished.
+    pub note: [u8; NOTE_LEN],
+    pub banned: [Pubkey; MAX_BANNED],
+    pub banned_count: u8,
+    /// Running total of `total_votes()`, repaired by `recompute_cache`.
+    pub cached_total_votes: u32,
+    pub is_paused: bool,
+    /// When non-empty, only these voters may cast signed votes.
+    pub whitelist: [Pubkey; MAX_WHITELIST],
+    pub whitelist_count: u8,
+    /// (voter, nonce) pairs used to vote, kept until the topic is reset.
+    pub seen_nonces: [(Pubkey, u64); MAX_SEEN_NONCES],
+    pub seen_nonce_count: u8,
+    /// Weight each whitelisted voter adds under `TallyMode::WhitelistWeight`.
+    pub whitelist_weights: [u64; MAX_WHITELIST],
+    /// Unix time of the last instruction that changed the topic, 0 if none has.
+    pub last_action_at: i64,
+    /// Options added over the topic's lifetime, kept across resets.
+    pub options_ever_added: u16,
+    /// Set by the owner once a finished result has been checked; cleared with the result.
+    pub certified: bool,
 }
 
 impl Default for Topic {
     fn default() -> Self {
         Topic {
             name: [0; 100],
-            options: [Option::default(); 10],
+            options: [Option::default(); MAX_OPTIONS],
             opt_current_idx: 0,
             owner: Pubkey::default(),
             result_idx: 0,
             is_finished: false,
+            nullifiers: [[0; 32]; MAX_NULLIFIERS],
+            nullifier_count: 0,
+            category: [0; 32],
+            config: TopicConfig::default(),
+            note: [0; NOTE_LEN],
+            banned: [Pubkey::default(); MAX_BANNED],
+            banned_count: 0,
+            cached_total_votes: 0,
+            is_paused: false,
+            whitelist: [Pubkey::default(); MAX_WHITELIST],
+            whitelist_count: 0,
+            seen_nonces: [(Pubkey::default(), 0); MAX_SEEN_NONCES],
+            seen_nonce_count: 0,
+            whitelist_weights: [0; MAX_WHITELIST],
+            last_action_at: 0,
+            options_ever_added: 0,
+            certified: false,
         }
     }
 }
@@ -32,217 +142,3400 @@ impl Default for Topic {
 impl Topic {
     pub fn new(name: &str, owner: &Pubkey) -> Topic {
         let mut name_data: [u8; 100] = [0; 100];
-        str_pack(name, &mut &mut name_data);
+        str_pack(name, &mut name_data);
         Topic {
             name: name_data,
-            options: [Option::default(); 10],
-            opt_current_idx: 0,
             owner: *owner,
-            result_idx: 0,
-            is_finished: false,
+            ..Topic::default()
         }
     }
 
+    /// A topic already holding `options`, in order, each validated as by `add_option`.
+    pub fn new_with_options(
+        name: &str,
+        owner: &Pubkey,
+        topic_key: &Pubkey,
+        options: &[&str],
+    ) -> Result<Topic, ProgramError> {
+        let mut topic = Topic::new(name, owner);
+        for opt_name in options {
+            topic.add_option(topic_key, opt_name)?;
+        }
+        Ok(topic)
+    }
+
+    /// Closes the topic and records the option with the most votes, the earliest one on a tie.
     pub fn finalize(&mut self) -> Result<(), ProgramError> {
+        let mut result_idx = 0;
+        let mut result_score = 0;
+        for (i, opt) in self.options_iter() {
+            if self.option_score(opt) > result_score {
+                result_idx = i;
+                result_score = self.option_score(opt);
+            }
+        }
         self.is_finished = true;
-        self.result_idx = 1;
+        self.result_idx = result_idx;
+        Ok(())
+    }
+
+    /// Marks a finished result as certified, after which `result_is_final` reports it.
+    pub fn certify(&mut self) -> Result<(), ProgramError> {
+        if !self.is_finished {
+            return Err(ProgramError::InvalidAccountData);
+        }
+        self.certified = true;
+        Ok(())
+    }
+
+    /// Whether the result is finished and certified, as opposed to merely tentative.
+    pub fn result_is_final(&self) -> bool {
+        self.is_finished && self.certified
+    }
+
+    /// Undoes `finalize`, leaving votes and options untouched. The note and certification
+    /// vouch for the result, so they go with it.
+    pub fn reset_result(&mut self) {
+        self.is_finished = false;
+        self.result_idx = 0;
+        self.note = [0; NOTE_LEN];
+        self.certified = false;
+    }
+
+    /// Clears options and votes while keeping the name, owner, settings and pause.
+    pub fn reset(&mut self) {
+        self.options = [Option::default(); MAX_OPTIONS];
+        self.opt_current_idx = 0;
+        self.result_idx = 0;
+        self.nullifiers = [[0; 32]; MAX_NULLIFIERS];
+        self.nullifier_count = 0;
+        self.note = [0; NOTE_LEN];
+        self.certified = false;
+        self.cached_total_votes = 0;
+        self.seen_nonces = [(Pubkey::default(), 0); MAX_SEEN_NONCES];
+        self.seen_nonce_count = 0;
+    }
+
+    pub fn state(&self) -> TopicState {
+        if self.name_is_empty() {
+            TopicState::Uninitialized
+        } else if self.is_finished {
+            TopicState::Finished
+        } else if self.is_paused {
+            TopicState::Paused
+        } else if self.is_draft() {
+            TopicState::Draft
+        } else {
+            TopicState::Open
+        }
+    }
+
+    /// Whether the topic is named but has fewer options than `min_options` (always at least
+    /// one) and no votes yet. Votes are only accepted once it leaves draft.
+    pub fn is_draft(&self) -> bool {
+        !self.name_is_empty()
+            && !self.is_finished
+            && self.options_iter().count() < self.config.min_options.max(1) as usize
+            && self.total_votes() == 0
+    }
+
+    /// Rebuilds the cached aggregates from the voter lists.
+    pub fn recompute_cache(&mut self) {
+        self.cached_total_votes = self.total_votes();
+    }
+
+    pub fn is_sealed(&self) -> bool {
+        self.is_finished && self.config.sealed_after_finish
+    }
+
+    pub fn quorum_reached(&self) -> bool {
+        self.total_votes() >= self.config.min_quorum
+    }
+
+    /// Changes `min_quorum`. Raising is always allowed; lowering only to a value the votes cast
+    /// already exceed, so the owner cannot make a stalled topic finishable on demand.
+    pub fn set_quorum(&mut self, min_quorum: u32) -> Result<(), ProgramError> {
+        if min_quorum < self.config.min_quorum && self.total_votes() <= min_quorum {
+            return Err(ProgramError::InvalidArgument);
+        }
+        if self.config.auto_finish_on_quorum && min_quorum == 0 {
+            return Err(ProgramError::InvalidArgument);
+        }
+        self.config.min_quorum = min_quorum;
+        Ok(())
+    }
+
+    /// Stamps `now` as the time of the latest change, failing with `TooSoon` while the
+    /// previous one is less than `min_action_interval` seconds old.
+    pub fn record_action(&mut self, now: i64) -> Result<(), ProgramError> {
+        if self.config.min_action_interval != 0
+            && self.last_action_at != 0
+            && now.saturating_sub(self.last_action_at) < self.config.min_action_interval
+        {
+            return Err(TopicError::TooSoon.into());
+        }
+        self.last_action_at = now;
         Ok(())
     }
 
-    pub fn set_name(&mut self, name: &str) {
+    /// Share of the quorum reached so far, clamped to 1.0 and 1.0 when no quorum is set.
+    pub fn quorum_progress(&self) -> f32 {
+        if self.config.min_quorum == 0 {
+            return 1.0;
+        }
+        (self.total_votes() as f32 / self.config.min_quorum as f32).min(1.0)
+    }
+
+    pub fn results_visible(&self) -> bool {
+        self.is_finished || !self.config.hide_results_until_finish
+    }
+
+    /// What an option is ranked by: its weight for weighted topics, otherwise its vote count.
+    pub fn option_score(&self, opt: &Option) -> u64 {
+        if self.config.is_weighted() {
+            opt.weight
+        } else {
+            opt.vote_count() as u64
+        }
+    }
+
+    pub fn set_name(&mut self, name: &str) -> Result<(), ProgramError> {
+        str_check(name)?;
         let mut name_data: [u8; 100] = [0; 100];
-        str_pack(name, &mut &mut name_data);
+        str_pack(name, &mut name_data);
         self.name = name_data;
+        Ok(())
+    }
+
+    pub fn is_owner(&self, key: &Pubkey) -> bool {
+        self.owner == *key
+    }
+
+    /// Checks that `owner` signed the instruction and is the topic's owner.
+    pub fn verify_owner_signer(&self, owner: &AccountInfo) -> Result<(), ProgramError> {
+        if !owner.is_signer {
+            return Err(ProgramError::MissingRequiredSignature);
+        }
+        if !self.is_owner(owner.key) {
+            return Err(TopicError::NotTopicOwner.into());
+        }
+        Ok(())
     }
 
     pub fn name_is_empty(&self) -> bool {
-        let name_str = str_unpack(&self.name);
-        name_str.is_empty()
+        matches!(str_unpack(&self.name), Ok(""))
+    }
+
+    /// Options that can still be added before reaching `max_options`.
+    pub fn options_remaining_capacity(&self) -> usize {
+        let max_options = match self.config.max_options {
+            0 => MAX_OPTIONS,
+            max => max as usize,
+        };
+        max_options.saturating_sub(self.opt_current_idx as usize)
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.options_remaining_capacity() == 0
+    }
+
+    /// Whether an option may be added now: the topic must be in `Draft` or `Open`, not full,
+    /// and not locked by a vote.
+    pub fn can_add_option(&self) -> Result<(), ProgramError> {
+        if !matches!(self.state(), TopicState::Draft | TopicState::Open) {
+            return Err(ProgramError::InvalidAccountData);
+        }
+        if self.is_full() {
+            return Err(TopicError::TopicFull.into());
+        }
+        if self.config.lock_options_on_first_vote && self.total_votes() > 0 {
+            return Err(TopicError::OptionsLocked.into());
+        }
+        Ok(())
     }
 
     pub fn add_option(&mut self, topic_key: &Pubkey, opt_name: &str) -> Result<(), ProgramError> {
-        if self.opt_current_idx as usize == self.options.len() - 1 {
+        self.can_add_option()?;
+        if self.config.distinct_names && str_unpack(&self.name)? == opt_name {
             return Err(ProgramError::InvalidArgument);
         }
-        let opt = Option::new(topic_key, self.opt_current_idx, opt_name);
+        let opt = Option::new(topic_key, self.opt_current_idx, opt_name)?;
         self.options[self.opt_current_idx as usize] = opt;
         self.opt_current_idx += 1;
+        self.options_ever_added = self.options_ever_added.saturating_add(1);
+        Ok(())
+    }
+
+    /// Moves the option at `order[i]` to slot `i`. Only active options may be permuted, the
+    /// remaining entries must leave their slot in place. Each option keeps its `belongs_idx`,
+    /// so it can still be found with `option_by_belongs_idx`.
+    pub fn reorder_options(&mut self, order: &[u8; MAX_OPTIONS]) -> Result<(), ProgramError> {
+        if self.total_votes() > 0 {
+            return Err(TopicError::OptionsLocked.into());
+        }
+        let active = self.opt_current_idx as usize;
+        let mut seen = [false; MAX_OPTIONS];
+        for (i, &from) in order.iter().enumerate() {
+            let from = from as usize;
+            let valid = if i < active { from < active } else { from == i };
+            if !valid || seen[from] {
+                return Err(ProgramError::InvalidArgument);
+            }
+            seen[from] = true;
+        }
+        let old_options = self.options;
+        for (i, &from) in order.iter().enumerate().take(active) {
+            self.options[i] = old_options[from as usize];
+        }
         Ok(())
     }
 
-    pub fn vote(&mut self, opt_idx: u8, voter: &Pubkey) -> Result<(), ProgramError> {
-        if self.opt_current_idx < opt_idx {
+    pub fn rename_option(&mut self, opt_idx: OptIdx, opt_name: &str) -> Result<(), ProgramError> {
+        let opt_idx = OptIdx::new(opt_idx.0, self)?;
+        if self.options[opt_idx.as_usize()].vote_count() > 0 {
+            return Err(TopicError::OptionHasVotes.into());
+        }
+        if self.config.distinct_names && str_unpack(&self.name)? == opt_name {
             return Err(ProgramError::InvalidArgument);
         }
-        self.options[opt_idx as usize].add_voter(voter)?;
+        self.options[opt_idx.as_usize()].set_name(opt_name)
+    }
+
+    pub fn vote(&mut self, opt_idx: OptIdx, voter: &Pubkey, now: i64) -> Result<(), ProgramError> {
+        let opt_idx = OptIdx::new(opt_idx.0, self)?;
+        self.options[opt_idx.as_usize()].add_voter(
+            voter,
+            now,
+            self.config.max_voters_per_option,
+        )?;
+        self.cached_total_votes = self.cached_total_votes.saturating_add(1);
         Ok(())
     }
 
-    pub fn unpack_from_slice(src: &[u8]) -> Result<Self, ProgramError> {
-        let src = array_ref!(src, 0, 11075);
-        let (name, options_bytes, opt_current_idx, owner, result_idx, is_finished) =
-            array_refs![src, MAX_TOPIC_NAME, 10940, 1, 32, 1, 1];
-        let mut options = [Option::default(); 10];
-        for (i, option) in options.iter_mut().enumerate() {
-            let (start, end) = (i * 1094, i * 1094 + 1094);
-            *option = Option::unpack_from_slice(&options_bytes[start..end])?;
+    /// Like `vote`, returning the option's vote count after the vote is recorded.
+    pub fn vote_with_receipt(
+        &mut self,
+        opt_idx: OptIdx,
+        voter: &Pubkey,
+        now: i64,
+    ) -> Result<u32, ProgramError> {
+        self.vote(opt_idx, voter, now)?;
+        Ok(self.options[opt_idx.as_usize()].vote_count())
+    }
+
+    /// Index of the option `voter` signed a vote for, if any.
+    pub fn voted_option(&self, voter: &Pubkey) -> std::option::Option<u8> {
+        self.options_iter()
+            .find(|(_, opt)| opt.active_voters().contains(voter))
+            .map(|(i, _)| i)
+    }
+
+    pub fn has_voted(&self, voter: &Pubkey) -> bool {
+        self.options
+            .iter()
+            .any(|opt| opt.active_voters().contains(voter))
+    }
+
+    /// Whether `voter` may cast a signed vote at `now`: the topic must be `Open` and before its
+    /// deadline, and the voter eligible, not banned and not yet voted.
+    pub fn can_vote(&self, voter: &Pubkey, now: i64) -> Result<(), ProgramError> {
+        self.check_accepting_votes(now)?;
+        if self.is_banned(voter) {
+            return Err(TopicError::VoterBanned.into());
         }
-        let opt_current_idx = opt_current_idx[0];
-        let owner = Pubkey::new(owner);
-        let result_idx = result_idx[0];
-        let is_finished = if is_finished[0] == 1 { true } else { false };
-        Ok(Topic {
-            name: *name,
-            options,
-            opt_current_idx,
-            owner,
-            result_idx,
-            is_finished,
-        })
+        if !self.is_eligible(voter) {
+            return Err(TopicError::VoterNotWhitelisted.into());
+        }
+        if self.has_voted(voter) {
+            return Err(TopicError::AlreadyVoted.into());
+        }
+        Ok(())
     }
 
-    pub fn pack_into_slice(&self, dst: &mut [u8]) {
-        let src = array_mut_ref!(dst, 0, 11075);
-        let (name, options_bytes, opt_current_idx, owner, result_idx, is_finished) =
-            mut_array_refs![src, MAX_TOPIC_NAME, 10940, 1, 32, 1, 1];
-        name.copy_from_slice(&self.name);
-        for (i, option) in self.options.iter().enumerate() {
-            let (start, end) = (i * 1094, i * 1094 + 1094);
-            option.pack_into_slice(&mut options_bytes[start..end]);
+    /// The topic must be `Open` and before its deadline.
+    pub(crate) fn check_accepting_votes(&self, now: i64) -> Result<(), ProgramError> {
+        match self.state() {
+            TopicState::Open => {}
+            TopicState::Paused => return Err(TopicError::TopicPaused.into()),
+            TopicState::Finished => return Err(TopicError::AlreadyFinished.into()),
+            TopicState::Uninitialized | TopicState::Draft => {
+                return Err(ProgramError::InvalidAccountData)
+            }
         }
-        opt_current_idx[0] = self.opt_current_idx;
-        owner.copy_from_slice(&self.owner.to_bytes());
-        result_idx[0] = self.result_idx;
-        if self.is_finished {
-            is_finished[0] = 1;
+        if self.config.deadline != 0 && now > self.config.deadline {
+            return Err(TopicError::DeadlinePassed.into());
         }
+        Ok(())
     }
 
-    pub fn empty_bytes() -> [u8; 12168] {
-        [0; 12168]
+    /// Whether `voter` may take back their signed vote at `now`, returning the option it is on.
+    /// Weighted topics do not record what each vote added, so their votes cannot be undone.
+    pub fn can_change_vote(&self, voter: &Pubkey, now: i64) -> Result<OptIdx, ProgramError> {
+        if !self.config.allow_vote_changes {
+            return Err(TopicError::VoteChangesDisabled.into());
+        }
+        self.check_accepting_votes(now)?;
+        if self.config.is_weighted() {
+            return Err(ProgramError::InvalidAccountData);
+        }
+        self.voted_option(voter)
+            .map(OptIdx)
+            .ok_or(ProgramError::InvalidArgument)
     }
 
-    pub fn get_packed_len() -> usize {
-        12168
+    /// Removes `voter`'s signed vote, returning the option it was on.
+    pub fn retract_vote(&mut self, voter: &Pubkey, now: i64) -> Result<OptIdx, ProgramError> {
+        let opt_idx = self.can_change_vote(voter, now)?;
+        self.options[opt_idx.as_usize()].remove_voter(voter)?;
+        self.cached_total_votes = self.cached_total_votes.saturating_sub(1);
+        Ok(opt_idx)
     }
-}
 
-#[repr(C)]
-#[derive(Clone, Copy, Debug, PartialEq)]
-pub struct Option {
-    belongs_to: Pubkey,
-    belongs_idx: u8,
-    name: [u8; 100],
-    voters: [Pubkey; 30],
-    current_voter_index: u8,
-}
+    /// Moves `voter`'s signed vote to `opt_idx`, returning that option's new vote count.
+    pub fn change_vote(
+        &mut self,
+        opt_idx: OptIdx,
+        voter: &Pubkey,
+        now: i64,
+    ) -> Result<u32, ProgramError> {
+        let opt_idx = OptIdx::new(opt_idx.0, self)?;
+        self.retract_vote(voter, now)?;
+        self.vote_with_receipt(opt_idx, voter, now)
+    }
 
-impl Default for Option {
-    fn default() -> Self {
-        Option {
-            belongs_to: Pubkey::default(),
-            belongs_idx: 0,
-            name: [0; 100],
-            voters: [Pubkey::default(); 30],
-            current_voter_index: 0,
+    pub fn has_token_account(&self, token_account: &Pubkey) -> bool {
+        self.options
+            .iter()
+            .any(|opt| opt.active_token_accounts().contains(token_account))
+    }
+
+    /// Votes like `vote`, also recording the token account backing the vote. A token account
+    /// may back only one vote, whichever wallet presents it.
+    pub fn vote_with_token_account(
+        &mut self,
+        opt_idx: OptIdx,
+        voter: &Pubkey,
+        token_account: &Pubkey,
+        now: i64,
+    ) -> Result<(), ProgramError> {
+        if self.has_token_account(token_account) {
+            return Err(TopicError::TokenAccountUsed.into());
         }
+        self.vote(opt_idx, voter, now)?;
+        let opt = &mut self.options[opt_idx.as_usize()];
+        opt.token_accounts[opt.current_voter_index as usize - 1] = *token_account;
+        Ok(())
     }
-}
 
-impl Option {
-    pub fn new(belongs_to: &Pubkey, belongs_idx: u8, name: &str) -> Option {
-        let mut name_data = [0; 100];
-        str_pack(name, &mut name_data);
-        Option {
-            belongs_to: *belongs_to,
-            belongs_idx,
-            name: name_data,
-            voters: [Pubkey::default(); 30],
-            current_voter_index: 0,
+    /// Votes like `vote_with_token_account`, backed by every account in `sources` and adding
+    /// their combined balance to the option's weight. All accounts must be held by `voter`,
+    /// share one mint and not have backed a vote before; the first one is recorded.
+    pub fn vote_with_token_balances(
+        &mut self,
+        opt_idx: OptIdx,
+        voter: &Pubkey,
+        sources: &[(Pubkey, TokenAccountData)],
+        now: i64,
+    ) -> Result<u32, ProgramError> {
+        let (first, first_data) = sources.first().ok_or(ProgramError::NotEnoughAccountKeys)?;
+        let mut weight: u64 = 0;
+        for (i, (key, data)) in sources.iter().enumerate() {
+            if data.mint != first_data.mint {
+                return Err(TopicError::TokenMintMismatch.into());
+            }
+            if data.owner != *voter {
+                return Err(ProgramError::IllegalOwner);
+            }
+            if self.has_token_account(key) || sources[..i].iter().any(|(k, _)| k == key) {
+                return Err(TopicError::TokenAccountUsed.into());
+            }
+            weight = weight
+                .checked_add(data.amount)
+                .ok_or(ProgramError::ArithmeticOverflow)?;
+        }
+        self.vote_with_token_account(opt_idx, voter, first, now)?;
+        let opt = &mut self.options[opt_idx.as_usize()];
+        opt.add_weight(weight)?;
+        Ok(opt.vote_count())
+    }
+
+    /// The option the topic was created with, `None` before any option exists.
+    pub fn first_option(&self) -> std::option::Option<&Option> {
+        self.options_iter().next().map(|(_, opt)| opt)
+    }
+
+    /// The option whose `belongs_idx` is `idx`, wherever a reorder has moved it.
+    pub fn option_by_belongs_idx(&self, idx: u8) -> std::option::Option<&Option> {
+        self.options_iter()
+            .map(|(_, opt)| opt)
+            .find(|opt| opt.belongs_idx == idx)
+    }
+
+    /// Initialized options paired with their slot index, in slot order.
+    pub fn options_iter(&self) -> impl Iterator<Item = (u8, &Option)> {
+        self.options
+            .iter()
+            .enumerate()
+            .filter(|(_, opt)| !opt.is_empty())
+            .map(|(i, opt)| (i as u8, opt))
+    }
+
+    pub fn total_votes(&self) -> u32 {
+        self.options_iter()
+            .fold(0, |total, (_, opt)| total.saturating_add(opt.vote_count()))
+    }
+
+    /// Sum of every active option's score, failing with `ArithmeticOverflow` past `u64::MAX`.
+    pub fn total_score(&self) -> Result<u64, ProgramError> {
+        self.options_iter().try_fold(0u64, |total, (_, opt)| {
+            total
+                .checked_add(self.option_score(opt))
+                .ok_or(ProgramError::ArithmeticOverflow)
+        })
+    }
+
+    /// Number of active options with the highest and second highest score.
+    fn leading_scores(&self) -> (usize, u64, u64) {
+        let (mut active, mut first, mut second) = (0, 0, 0);
+        for (_, opt) in self.options_iter() {
+            let score = self.option_score(opt);
+            active += 1;
+            if score > first {
+                second = first;
+                first = score;
+            } else if score > second {
+                second = score;
+            }
+        }
+        (active, first, second)
+    }
+
+    /// Whether the two leading active options currently have the same number of votes.
+    pub fn needs_tiebreak(&self) -> bool {
+        let (active, first, second) = self.leading_scores();
+        active >= 2 && first == second
+    }
+
+    /// The leader's margin over the runner-up as a percentage of all votes, 0.0 without votes.
+    pub fn winning_margin_percent(&self) -> f32 {
+        let total: u128 = self
+            .options_iter()
+            .map(|(_, opt)| self.option_score(opt) as u128)
+            .sum();
+        if total == 0 {
+            return 0.0;
+        }
+        let (_, first, second) = self.leading_scores();
+        (first - second) as f32 / total as f32 * 100.0
+    }
+
+    /// One-line summary of a finished topic's winner, e.g. `'Pizza' won with 12 of 20 votes (60%)`.
+    /// Weighted topics are described by the score that decided them, in units of weight.
+    pub fn describe_result(&self) -> Result<String, ProgramError> {
+        if !self.is_finished {
+            return Err(ProgramError::InvalidAccountData);
+        }
+        let winner = self
+            .options
+            .get(self.result_idx as usize)
+            .ok_or(ProgramError::InvalidAccountData)?;
+        let score = self.option_score(winner);
+        let total = self.total_score()?;
+        let percent = if total == 0 {
+            0
+        } else {
+            score as u128 * 100 / total as u128
+        };
+        let unit = if self.config.is_weighted() {
+            "weight"
+        } else {
+            "votes"
+        };
+        Ok(format!(
+            "'{}' won with {} of {} {} ({}%)",
+            str_unpack(&winner.name)?,
+            score,
+            total,
+            unit,
+            percent
+        ))
+    }
+
+    /// Signed voters of the option at `idx`, in the order they voted.
+    pub fn option_voters(&self, idx: u8) -> Result<&[Pubkey], ProgramError> {
+        let opt_idx = OptIdx::new(idx, self)?;
+        Ok(self.options[opt_idx.as_usize()].active_voters())
+    }
+
+    /// Vote counts of the options at `indices`, in the same order.
+    pub fn counts_for(&self, indices: &[u8]) -> Result<Vec<u32>, ProgramError> {
+        indices
+            .iter()
+            .map(|&idx| {
+                let opt_idx = OptIdx::new(idx, self)?;
+                Ok(self.options[opt_idx.as_usize()].vote_count())
+            })
+            .collect()
+    }
+
+    /// Signed voters of a finished topic's winning option, in the order they voted.
+    pub fn winner_voters(&self) -> Result<Vec<Pubkey>, ProgramError> {
+        if !self.is_finished {
+            return Err(ProgramError::InvalidAccountData);
         }
+        Ok(self.option_voters(self.result_idx)?.to_vec())
+    }
+
+    /// Each active option's name with its signed voters, for off-chain audits.
+    pub fn options_with_votes(&self) -> Vec<(String, Vec<Pubkey>)> {
+        self.options_iter()
+            .map(|(_, opt)| (str_unpack_lossy(&opt.name), opt.active_voters().to_vec()))
+            .collect()
+    }
+
+    /// Each active option's index with its share of all votes, 0.0 for all when nobody voted.
+    pub fn vote_percentages(&self) -> Vec<(u8, f32)> {
+        let total = self.total_votes();
+        self.options_iter()
+            .map(|(i, opt)| {
+                let share = if total == 0 {
+                    0.0
+                } else {
+                    opt.vote_count() as f32 / total as f32
+                };
+                (i, share)
+            })
+            .collect()
     }
 
-    pub fn add_voter(&mut self, voter: &Pubkey) -> Result<(), ProgramError> {
-        if self.current_voter_index as usize == self.voters.len() - 1 {
+    /// Weight accumulated by an option under the topic's tally mode, independent of its voter
+    /// count.
+    pub fn option_weight(&self, idx: u8) -> Result<u64, ProgramError> {
+        let opt_idx = OptIdx::new(idx, self)?;
+        Ok(self.options[opt_idx.as_usize()].weight)
+    }
+
+    /// Per-option vote counts summed with another shard of the same poll, whose active options
+    /// must carry the same names in the same slots.
+    pub fn merge_tally(&self, other: &Topic) -> Result<Vec<(u8, u32)>, ProgramError> {
+        if self.options_iter().count() != other.options_iter().count() {
             return Err(ProgramError::InvalidArgument);
         }
+        self.options_iter()
+            .zip(other.options_iter())
+            .map(|((i, opt), (j, other_opt))| {
+                if i != j || str_unpack(&opt.name)? != str_unpack(&other_opt.name)? {
+                    return Err(ProgramError::InvalidArgument);
+                }
+                let votes = opt
+                    .vote_count()
+                    .checked_add(other_opt.vote_count())
+                    .ok_or(ProgramError::ArithmeticOverflow)?;
+                Ok((i, votes))
+            })
+            .collect()
+    }
 
-        self.voters[self.current_voter_index as usize] = *voter;
-        self.current_voter_index += 1;
+    /// Earliest recorded vote time across all options, `None` if nobody voted.
+    pub fn first_voter_time(&self) -> std::option::Option<i64> {
+        self.options
+            .iter()
+            .flat_map(|opt| opt.active_vote_times())
+            .copied()
+            .min()
+    }
+
+    /// Latest recorded vote time across all options, `None` if nobody voted.
+    pub fn last_voter_time(&self) -> std::option::Option<i64> {
+        self.options
+            .iter()
+            .flat_map(|opt| opt.active_vote_times())
+            .copied()
+            .max()
+    }
+
+    pub fn is_banned(&self, voter: &Pubkey) -> bool {
+        self.banned[..self.banned_count as usize].contains(voter)
+    }
+
+    /// Bans a voter from this topic, a no-op if they are already banned.
+    pub fn ban_voter(&mut self, voter: &Pubkey) -> Result<(), ProgramError> {
+        if self.is_banned(voter) {
+            return Ok(());
+        }
+        if self.banned_count as usize == self.banned.len() {
+            return Err(ProgramError::InvalidArgument);
+        }
+        self.banned[self.banned_count as usize] = *voter;
+        self.banned_count += 1;
         Ok(())
     }
 
-    pub fn unpack_from_slice(src: &[u8]) -> Result<Self, ProgramError> {
-        let src = array_ref!(src, 0, 1094);
-        let (belongs_to, belongs_idx, name, voters_bytes, current_voter_index) =
-            array_refs![src, 32, 1, MAX_OPTION_NAME, 960, 1];
-        let belongs_to = Pubkey::new(belongs_to);
-        let belongs_idx = belongs_idx[0];
-        let mut voters = [Pubkey::new_unique(); 30];
-        for (i, voter) in voters.iter_mut().enumerate() {
-            let (start, end) = (i * 32, i * 32 + 32);
-            *voter = Pubkey::new(&voters_bytes[start..end]);
+    pub fn is_whitelisted(&self, voter: &Pubkey) -> bool {
+        self.whitelist[..self.whitelist_count as usize].contains(voter)
+    }
+
+    /// Whether `voter` may cast a signed vote: anyone when no whitelist is set.
+    pub fn is_eligible(&self, voter: &Pubkey) -> bool {
+        self.whitelist_count == 0 || self.is_whitelisted(voter)
+    }
+
+    /// Adds a voter to the whitelist with `weight`, updating the weight if they are already on it.
+    pub fn add_whitelisted(&mut self, voter: &Pubkey, weight: u64) -> Result<(), ProgramError> {
+        let active = &self.whitelist[..self.whitelist_count as usize];
+        if let Some(i) = active.iter().position(|listed| listed == voter) {
+            self.whitelist_weights[i] = weight;
+            return Ok(());
         }
-        let current_voter_index = current_voter_index[0];
+        if self.whitelist_count as usize == self.whitelist.len() {
+            return Err(ProgramError::InvalidArgument);
+        }
+        self.whitelist[self.whitelist_count as usize] = *voter;
+        self.whitelist_weights[self.whitelist_count as usize] = weight;
+        self.whitelist_count += 1;
+        Ok(())
+    }
 
-        Ok(Option {
-            belongs_to,
-            belongs_idx,
-            name: *name,
-            voters,
-            current_voter_index,
-        })
+    /// Weight `voter` was whitelisted with, 0 when not on the whitelist.
+    pub fn whitelist_weight(&self, voter: &Pubkey) -> u64 {
+        self.whitelist[..self.whitelist_count as usize]
+            .iter()
+            .position(|listed| listed == voter)
+            .map_or(0, |i| self.whitelist_weights[i])
     }
 
-    pub fn pack_into_slice(&self, dst: &mut [u8]) {
-        let src = array_mut_ref![dst, 0, 1094];
-        let (belongs_to, belongs_idx, name, voters_bytes, current_voter_index) =
-            mut_array_refs![src, 32, 1, MAX_OPTION_NAME, 960, 1];
-        belongs_to.copy_from_slice(&self.belongs_to.to_bytes());
-        name.copy_from_slice(&self.name);
-        belongs_idx[0] = self.belongs_idx;
-        for (i, pubkey) in self.voters.iter().enumerate() {
-            let (start, end) = (i * 32, i * 32 + 32);
-            voters_bytes[start..end].copy_from_slice(&pubkey.to_bytes());
+    /// Rejects active options whose `belongs_idx` values are not a permutation of their slots,
+    /// as in a corrupted account.
+    pub fn validate_option_indices(&self) -> Result<(), ProgramError> {
+        let active = self
+            .options
+            .get(..self.opt_current_idx as usize)
+            .ok_or(ProgramError::InvalidAccountData)?;
+        let mut seen = [false; MAX_OPTIONS];
+        for opt in active {
+            let idx = opt.belongs_idx as usize;
+            if idx >= active.len() || seen[idx] {
+                return Err(ProgramError::InvalidAccountData);
+            }
+            seen[idx] = true;
         }
-        current_voter_index[0] = self.current_voter_index;
+        Ok(())
     }
 
-    pub fn empty_bytes() -> [u8; 1094] {
-        [0; 1094]
+    /// Rejects counts and cursors past the end of the arrays they index, as in a corrupted
+    /// account.
+    pub fn validate_counts(&self) -> Result<(), ProgramError> {
+        if self.nullifier_count as usize > MAX_NULLIFIERS
+            || self.banned_count as usize > MAX_BANNED
+            || self.whitelist_count as usize > MAX_WHITELIST
+            || self.seen_nonce_count as usize > MAX_SEEN_NONCES
+        {
+            return Err(ProgramError::InvalidAccountData);
+        }
+        Ok(())
     }
 
-    pub fn get_packed_len() -> usize {
-        1094
+    /// Records `nonce` as used by `voter`, failing with `NonceReused` if it already was and
+    /// with `InvalidArgument` once `MAX_SEEN_NONCES` pairs are recorded, so none is forgotten.
+    pub fn use_nonce(&mut self, voter: &Pubkey, nonce: u64) -> Result<(), ProgramError> {
+        let seen = &self.seen_nonces[..self.seen_nonce_count as usize];
+        if seen.contains(&(*voter, nonce)) {
+            return Err(TopicError::NonceReused.into());
+        }
+        if seen.len() == self.seen_nonces.len() {
+            return Err(ProgramError::InvalidArgument);
+        }
+        self.seen_nonces[self.seen_nonce_count as usize] = (*voter, nonce);
+        self.seen_nonce_count += 1;
+        Ok(())
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use crate::state::{Option, Topic};
-    use solana_program::pubkey::Pubkey;
+    /// Size of the whitelist, or `OPEN_VOTING` when anyone may vote.
+    pub fn eligible_voter_count(&self) -> u32 {
+        match self.whitelist_count {
+            0 => OPEN_VOTING,
+            count => count as u32,
+        }
+    }
 
-    #[test]
-    fn test_option_pack_unpack() {
-        let pkey = Pubkey::default();
-        let mut opt = Option::new(&pkey, 0, "test_option");
-        let mut data = Option::empty_bytes();
-        opt.add_voter(&Pubkey::new_unique()).unwrap();
-        opt.pack_into_slice(&mut data[..]);
+    /// Distinct signed voters across all options, anonymous votes excluded.
+    pub fn unique_voter_count(&self) -> u32 {
+        let mut seen: Vec<&Pubkey> = Vec::new();
+        for (_, opt) in self.options_iter() {
+            for voter in &opt.voters[..opt.current_voter_index as usize] {
+                if !seen.contains(&voter) {
+                    seen.push(voter);
+                }
+            }
+        }
+        seen.len() as u32
+    }
 
-        let opt2 = Option::unpack_from_slice(&data).unwrap();
-        assert_eq!(opt, opt2);
+    /// Share of the whitelist that has cast a signed vote. Open topics have no bound to
+    /// compare against, so they report the raw `unique_voter_count` instead. A topic nobody
+    /// has voted on reports 0 either way.
+    pub fn turnout(&self) -> f32 {
+        match self.eligible_voter_count() {
+            OPEN_VOTING => self.unique_voter_count() as f32,
+            eligible => self.unique_voter_count() as f32 / eligible as f32,
+        }
     }
 
-    #[test]
-    fn test_topic_pack_unpack() {
-        let pk = Pubkey::new_unique();
-        let mut topic = Topic::new("test_topic", &pk);
-        topic.add_option(&pk, "option_name").unwrap();
-        let mut data = Topic::empty_bytes();
-        topic.pack_into_slice(&mut data);
+    pub fn has_nullifier(&self, nullifier: &[u8; 32]) -> bool {
+        self.nullifiers[..self.nullifier_count as usize].contains(nullifier)
+    }
 
-        let topic2 = Topic::unpack_from_slice(&mut data).unwrap();
+    /// Records an anonymous vote, rejecting a nullifier that was already used.
+    pub fn anon_vote(&mut self, opt_idx: OptIdx, nullifier: &[u8; 32]) -> Result<(), ProgramError> {
+        let opt_idx = OptIdx::new(opt_idx.0, self)?;
+        if self.has_nullifier(nullifier) {
+            return Err(ProgramError::InvalidArgument);
+        }
+        if self.nullifier_count as usize == self.nullifiers.len() {
+            return Err(ProgramError::InvalidArgument);
+        }
+        self.options[opt_idx.as_usize()].add_anon_vote()?;
+        self.nullifiers[self.nullifier_count as usize] = *nullifier;
+        self.nullifier_count += 1;
+        self.cached_total_votes = self.cached_total_votes.saturating_add(1);
+        Ok(())
+    }
+
+    /// Nullifier an anonymous voter derives from `secret`. The topic's salt makes the same
+    /// secret give unrelated nullifiers on different topics.
+    pub fn nullifier(&self, secret: &[u8; 32]) -> [u8; 32] {
+        hashv(&[b"nullifier", &self.config.salt, secret]).to_bytes()
+    }
+
+    /// Commitment to voting for `opt_idx`, opened later by revealing `secret`.
+    pub fn commitment(&self, opt_idx: OptIdx, secret: &[u8; 32]) -> [u8; 32] {
+        hashv(&[b"commitment", &self.config.salt, &[opt_idx.0], secret]).to_bytes()
+    }
+
+    /// Loads a topic from an account, checking that it is owned by the program.
+    pub fn from_account_info(
+        acc: &AccountInfo,
+        program_id: &Pubkey,
+    ) -> Result<Topic, ProgramError> {
+        if acc.owner != program_id {
+            return Err(TopicError::WrongProgramOwner.into());
+        }
+        Topic::unpack_from_slice(&acc.data.borrow())
+    }
+
+    /// Packs the topic back into an account.
+    pub fn save(&self, acc: &AccountInfo) -> Result<(), ProgramError> {
+        self.pack_into_slice(&mut acc.data.borrow_mut())
+    }
+
+    /// Unpacks a topic from `src`, failing with `InvalidAccountData` if it is too short.
+    pub fn unpack_from_slice(src: &[u8]) -> Result<Self, ProgramError> {
+        if src.len() < TOPIC_LEN {
+            return Err(ProgramError::InvalidAccountData);
+        }
+        let src = array_ref!(src, 0, TOPIC_LEN);
+        let (
+            name,
+            options_bytes,
+            opt_current_idx,
+            owner,
+            result_idx,
+            is_finished,
+            nullifiers_bytes,
+            nullifier_count,
+            category,
+            config,
+            note,
+            banned_bytes,
+            banned_count,
+            cached_total_votes,
+            is_paused,
+            whitelist_bytes,
+            whitelist_count,
+            seen_nonces_bytes,
+            seen_nonce_count,
+            whitelist_weights_bytes,
+            last_action_at,
+            options_ever_added,
+            certified,
+        ) = array_refs![
+            src,
+            MAX_TOPIC_NAME,
+            OPTION_LEN * MAX_OPTIONS,
+            1,
+            32,
+            1,
+            1,
+            32 * MAX_NULLIFIERS,
+            1,
+            32,
+            CONFIG_LEN,
+            NOTE_LEN,
+            32 * MAX_BANNED,
+            1,
+            4,
+            1,
+            32 * MAX_WHITELIST,
+            1,
+            40 * MAX_SEEN_NONCES,
+            1,
+            8 * MAX_WHITELIST,
+            8,
+            2,
+            1
+        ];
+        // Slots from `opt_current_idx` on are never written, so only the active ones are parsed.
+        let opt_current_idx = opt_current_idx[0];
+        let mut options = [Option::default(); MAX_OPTIONS];
+        let active = (opt_current_idx as usize).min(MAX_OPTIONS);
+        for (i, option) in options.iter_mut().enumerate().take(active) {
+            let (start, end) = (i * OPTION_LEN, i * OPTION_LEN + OPTION_LEN);
+            *option = Option::unpack_from_slice(&options_bytes[start..end])?;
+        }
+        let owner = Pubkey::new_from_array(*owner);
+        let result_idx = result_idx[0];
+        let is_finished = is_finished[0] == 1;
+        let mut nullifiers = [[0; 32]; MAX_NULLIFIERS];
+        for (i, nullifier) in nullifiers.iter_mut().enumerate() {
+            *nullifier = *array_ref!(nullifiers_bytes, i * 32, 32);
+        }
+        let nullifier_count = nullifier_count[0];
+        let mut banned = [Pubkey::default(); MAX_BANNED];
+        for (i, voter) in banned.iter_mut().enumerate() {
+            *voter = Pubkey::new_from_array(*array_ref!(banned_bytes, i * 32, 32));
+        }
+        let mut whitelist = [Pubkey::default(); MAX_WHITELIST];
+        for (i, voter) in whitelist.iter_mut().enumerate() {
+            *voter = Pubkey::new_from_array(*array_ref!(whitelist_bytes, i * 32, 32));
+        }
+        let mut seen_nonces = [(Pubkey::default(), 0); MAX_SEEN_NONCES];
+        for (i, (voter, nonce)) in seen_nonces.iter_mut().enumerate() {
+            let (voter_bytes, nonce_bytes) =
+                array_refs![array_ref!(seen_nonces_bytes, i * 40, 40), 32, 8];
+            *voter = Pubkey::new_from_array(*voter_bytes);
+            *nonce = unpack_u64(nonce_bytes);
+        }
+        let mut whitelist_weights = [0; MAX_WHITELIST];
+        for (i, weight) in whitelist_weights.iter_mut().enumerate() {
+            *weight = unpack_u64(array_ref!(whitelist_weights_bytes, i * 8, 8));
+        }
+        let topic = Topic {
+            name: *name,
+            options,
+            opt_current_idx,
+            owner,
+            result_idx,
+            is_finished,
+            nullifiers,
+            nullifier_count,
+            category: *category,
+            config: TopicConfig::unpack_from_slice(config)?,
+            note: *note,
+            banned,
+            banned_count: banned_count[0],
+            cached_total_votes: unpack_u32(cached_total_votes),
+            is_paused: is_paused[0] == 1,
+            whitelist,
+            whitelist_count: whitelist_count[0],
+            seen_nonces,
+            seen_nonce_count: seen_nonce_count[0],
+            whitelist_weights,
+            last_action_at: unpack_i64(last_action_at),
+            options_ever_added: u16::from_le_bytes(*options_ever_added),
+            certified: certified[0] == 1,
+        };
+        topic.validate_option_indices()?;
+        topic.validate_counts()?;
+        Ok(topic)
+    }
+
+    /// Packs the topic into `dst`, failing with `InvalidAccountData` if it is too short.
+    pub fn pack_into_slice(&self, dst: &mut [u8]) -> Result<(), ProgramError> {
+        if dst.len() < TOPIC_LEN {
+            return Err(ProgramError::InvalidAccountData);
+        }
+        let src = array_mut_ref!(dst, 0, TOPIC_LEN);
+        let (
+            name,
+            options_bytes,
+            opt_current_idx,
+            owner,
+            result_idx,
+            is_finished,
+            nullifiers_bytes,
+            nullifier_count,
+            category,
+            config,
+            note_dst,
+            banned_bytes,
+            banned_count,
+            cached_total_votes,
+            is_paused,
+            whitelist_bytes,
+            whitelist_count,
+            seen_nonces_bytes,
+            seen_nonce_count,
+            whitelist_weights_bytes,
+            last_action_at,
+            options_ever_added,
+            certified,
+        ) = mut_array_refs![
+            src,
+            MAX_TOPIC_NAME,
+            OPTION_LEN * MAX_OPTIONS,
+            1,
+            32,
+            1,
+            1,
+            32 * MAX_NULLIFIERS,
+            1,
+            32,
+            CONFIG_LEN,
+            NOTE_LEN,
+            32 * MAX_BANNED,
+            1,
+            4,
+            1,
+            32 * MAX_WHITELIST,
+            1,
+            40 * MAX_SEEN_NONCES,
+            1,
+            8 * MAX_WHITELIST,
+            8,
+            2,
+            1
+        ];
+        name.copy_from_slice(&self.name);
+        let active = (self.opt_current_idx as usize).min(MAX_OPTIONS);
+        for (i, option) in self.options.iter().enumerate().take(active) {
+            let (start, end) = (i * OPTION_LEN, i * OPTION_LEN + OPTION_LEN);
+            option.pack_into_slice(&mut options_bytes[start..end]);
+        }
+        options_bytes[active * OPTION_LEN..].fill(0);
+        opt_current_idx[0] = self.opt_current_idx;
+        owner.copy_from_slice(&self.owner.to_bytes());
+        result_idx[0] = self.result_idx;
+        is_finished[0] = self.is_finished as u8;
+        for (i, nullifier) in self.nullifiers.iter().enumerate() {
+            nullifiers_bytes[i * 32..i * 32 + 32].copy_from_slice(nullifier);
+        }
+        nullifier_count[0] = self.nullifier_count;
+        category.copy_from_slice(&self.category);
+        self.config.pack_into_slice(config);
+        note_dst.copy_from_slice(&self.note);
+        for (i, voter) in self.banned.iter().enumerate() {
+            banned_bytes[i * 32..i * 32 + 32].copy_from_slice(voter.as_ref());
+        }
+        banned_count[0] = self.banned_count;
+        pack_u32(self.cached_total_votes, cached_total_votes);
+        is_paused[0] = self.is_paused as u8;
+        for (i, voter) in self.whitelist.iter().enumerate() {
+            whitelist_bytes[i * 32..i * 32 + 32].copy_from_slice(voter.as_ref());
+        }
+        whitelist_count[0] = self.whitelist_count;
+        for (i, (voter, nonce)) in self.seen_nonces.iter().enumerate() {
+            let (voter_bytes, nonce_bytes) =
+                mut_array_refs![array_mut_ref!(seen_nonces_bytes, i * 40, 40), 32, 8];
+            voter_bytes.copy_from_slice(voter.as_ref());
+            pack_u64(*nonce, nonce_bytes);
+        }
+        seen_nonce_count[0] = self.seen_nonce_count;
+        for (i, weight) in self.whitelist_weights.iter().enumerate() {
+            pack_u64(*weight, array_mut_ref!(whitelist_weights_bytes, i * 8, 8));
+        }
+        pack_i64(self.last_action_at, last_action_at);
+        *options_ever_added = self.options_ever_added.to_le_bytes();
+        certified[0] = self.certified as u8;
+        Ok(())
+    }
+
+    /// SHA-256 of the packed topic, for comparing state off-chain.
+    pub fn snapshot_hash(&self) -> [u8; 32] {
+        let mut buf = vec![0; TOPIC_LEN];
+        self.pack_into_slice(&mut buf)
+            .expect("buffer is TOPIC_LEN bytes");
+        hash(&buf).to_bytes()
+    }
+
+    pub fn empty_bytes() -> [u8; TOPIC_LEN] {
+        [0; TOPIC_LEN]
+    }
+
+    pub fn get_packed_len() -> usize {
+        TOPIC_LEN
+    }
+}
+
+/// How signed votes are turned into an option's score.
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum TallyMode {
+    /// Every vote counts once, or by balance with `weight_by_lamports`.
+    #[default]
+    Standard = 0,
+    /// Each vote adds the integer square root of the lamports the voter commits.
+    Quadratic = 1,
+    /// Each vote adds the weight the voter was whitelisted with.
+    WhitelistWeight = 2,
+    /// Each vote adds the combined balance of the token accounts backing it.
+    TokenWeight = 3,
+}
+
+impl TallyMode {
+    /// Score a quadratic vote committing `amount` lamports adds.
+    pub fn quadratic_weight(amount: u64) -> u64 {
+        isqrt(amount)
+    }
+}
+
+impl TryFrom<u8> for TallyMode {
+    type Error = ProgramError;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            0 => Ok(TallyMode::Standard),
+            1 => Ok(TallyMode::Quadratic),
+            2 => Ok(TallyMode::WhitelistWeight),
+            3 => Ok(TallyMode::TokenWeight),
+            _ => Err(ProgramError::InvalidAccountData),
+        }
+    }
+}
+
+/// Owner of the SPL token accounts `TallyMode::TokenWeight` votes are backed by.
+pub const TOKEN_PROGRAM_ID: Pubkey =
+    solana_program::pubkey!("TokenkegQfeZyiNwAJbNbGKPFXCWuBvf9Ss623VQ5DA");
+
+/// Leading fields of an SPL token account, read without depending on the token program.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TokenAccountData {
+    pub mint: Pubkey,
+    pub owner: Pubkey,
+    pub amount: u64,
+}
+
+impl TokenAccountData {
+    pub fn unpack_from_slice(src: &[u8]) -> Result<Self, ProgramError> {
+        if src.len() < 72 {
+            return Err(ProgramError::InvalidAccountData);
+        }
+        let (mint, owner, amount) = array_refs![array_ref!(src, 0, 72), 32, 32, 8];
+        Ok(TokenAccountData {
+            mint: Pubkey::new_from_array(*mint),
+            owner: Pubkey::new_from_array(*owner),
+            amount: unpack_u64(amount),
+        })
+    }
+}
+
+/// Layout of an oracle account: the voter it rates, then their weight.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct OracleWeight {
+    pub voter: Pubkey,
+    pub weight: u64,
+}
+
+impl OracleWeight {
+    pub fn unpack_from_slice(src: &[u8]) -> Result<Self, ProgramError> {
+        if src.len() < 40 {
+            return Err(ProgramError::InvalidAccountData);
+        }
+        let (voter, weight) = array_refs![array_ref!(src, 0, 40), 32, 8];
+        Ok(OracleWeight {
+            voter: Pubkey::new_from_array(*voter),
+            weight: unpack_u64(weight),
+        })
+    }
+}
+
+/// Settings chosen by the owner when the topic is created.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TopicConfig {
+    pub distinct_names: bool,
+    pub hide_results_until_finish: bool,
+    pub lock_options_on_first_vote: bool,
+    /// Lamports a voter account must hold to vote, 0 to allow any voter.
+    pub min_voter_lamports: u64,
+    /// Votes needed before the topic can be finished, 0 for no quorum.
+    pub min_quorum: u32,
+    /// Count votes by the voter's balance in whole SOL instead of one per voter.
+    pub weight_by_lamports: bool,
+    /// Once finished, the topic can no longer be reset or reopened.
+    pub sealed_after_finish: bool,
+    /// Voters each option accepts, at most 30, 0 for no limit beyond the option's capacity.
+    pub max_voters_per_option: u8,
+    /// Share of the topic's lamports, in basis points, paid to `fee_destination` on close.
+    pub fee_bps: u16,
+    pub fee_destination: Pubkey,
+    /// Options the topic accepts, at most 10, 0 for no limit beyond the topic's capacity.
+    pub max_options: u8,
+    /// Votes must also be signed by `cosigner` to count.
+    pub require_cosigner: bool,
+    pub cosigner: Pubkey,
+    /// Unix time after which signed votes are rejected, 0 for no deadline.
+    pub deadline: i64,
+    pub tally_mode: TallyMode,
+    /// Finish the topic on the vote that reaches `min_quorum`, which must then be set.
+    pub auto_finish_on_quorum: bool,
+    /// Seconds that must pass between instructions changing the topic, 0 for no cooldown.
+    pub min_action_interval: i64,
+    /// Voters may retract or change their signed vote while the topic is open.
+    pub allow_vote_changes: bool,
+    /// Signed votes pass an oracle account whose weight for the voter is added to the option.
+    pub oracle_weighted: bool,
+    /// Program that must own the oracle accounts of an `oracle_weighted` topic.
+    pub oracle_authority: Pubkey,
+    /// Options the topic needs before it opens for votes, at most 10, 0 or 1 for one.
+    pub min_options: u8,
+    /// Random bytes chosen by the client, mixed into nullifiers and commitments.
+    pub salt: [u8; 16],
+    /// Mint that the token account backing a `TokenVote` must hold.
+    pub token_mint: Pubkey,
+}
+
+impl Default for TopicConfig {
+    fn default() -> Self {
+        TopicConfig {
+            distinct_names: false,
+            hide_results_until_finish: false,
+            lock_options_on_first_vote: false,
+            min_voter_lamports: 0,
+            min_quorum: 0,
+            weight_by_lamports: false,
+            sealed_after_finish: false,
+            max_voters_per_option: 0,
+            fee_bps: 0,
+            fee_destination: Pubkey::default(),
+            max_options: 0,
+            require_cosigner: false,
+            cosigner: Pubkey::default(),
+            deadline: 0,
+            tally_mode: TallyMode::Standard,
+            auto_finish_on_quorum: false,
+            min_action_interval: 0,
+            allow_vote_changes: true,
+            oracle_weighted: false,
+            oracle_authority: Pubkey::default(),
+            min_options: 0,
+            salt: [0; 16],
+            token_mint: Pubkey::default(),
+        }
+    }
+}
+
+impl TopicConfig {
+    pub fn unpack_from_slice(src: &[u8]) -> Result<Self, ProgramError> {
+        let src = array_ref!(src, 0, CONFIG_LEN);
+        let (
+            distinct_names,
+            hide_results_until_finish,
+            lock_options_on_first_vote,
+            min_voter_lamports,
+            min_quorum,
+            weight_by_lamports,
+            sealed_after_finish,
+            max_voters_per_option,
+            fee_bps,
+            fee_destination,
+            max_options,
+            require_cosigner,
+            cosigner,
+            deadline,
+            tally_mode,
+            auto_finish_on_quorum,
+            min_action_interval,
+            allow_vote_changes,
+            oracle_weighted,
+            oracle_authority,
+            min_options,
+            salt,
+            token_mint,
+        ) = array_refs![
+            src, 1, 1, 1, 8, 4, 1, 1, 1, 2, 32, 1, 1, 32, 8, 1, 1, 8, 1, 1, 32, 1, 16, 32
+        ];
+        Ok(TopicConfig {
+            distinct_names: distinct_names[0] == 1,
+            hide_results_until_finish: hide_results_until_finish[0] == 1,
+            lock_options_on_first_vote: lock_options_on_first_vote[0] == 1,
+            min_voter_lamports: unpack_u64(min_voter_lamports),
+            min_quorum: unpack_u32(min_quorum),
+            weight_by_lamports: weight_by_lamports[0] == 1,
+            sealed_after_finish: sealed_after_finish[0] == 1,
+            max_voters_per_option: max_voters_per_option[0],
+            fee_bps: u16::from_le_bytes(*fee_bps),
+            fee_destination: Pubkey::new_from_array(*fee_destination),
+            max_options: max_options[0],
+            require_cosigner: require_cosigner[0] == 1,
+            cosigner: Pubkey::new_from_array(*cosigner),
+            deadline: unpack_i64(deadline),
+            tally_mode: TallyMode::try_from(tally_mode[0])?,
+            auto_finish_on_quorum: auto_finish_on_quorum[0] == 1,
+            min_action_interval: unpack_i64(min_action_interval),
+            allow_vote_changes: allow_vote_changes[0] == 1,
+            oracle_weighted: oracle_weighted[0] == 1,
+            oracle_authority: Pubkey::new_from_array(*oracle_authority),
+            min_options: min_options[0],
+            salt: *salt,
+            token_mint: Pubkey::new_from_array(*token_mint),
+        })
+    }
+
+    pub fn pack_into_slice(&self, dst: &mut [u8]) {
+        let dst = array_mut_ref!(dst, 0, CONFIG_LEN);
+        let (
+            distinct_names,
+            hide_results_until_finish,
+            lock_options_on_first_vote,
+            min_voter_lamports,
+            min_quorum,
+            weight_by_lamports,
+            sealed_after_finish,
+            max_voters_per_option,
+            fee_bps,
+            fee_destination,
+            max_options,
+            require_cosigner,
+            cosigner,
+            deadline,
+            tally_mode,
+            auto_finish_on_quorum,
+            min_action_interval,
+            allow_vote_changes,
+            oracle_weighted,
+            oracle_authority,
+            min_options,
+            salt,
+            token_mint,
+        ) = mut_array_refs![
+            dst, 1, 1, 1, 8, 4, 1, 1, 1, 2, 32, 1, 1, 32, 8, 1, 1, 8, 1, 1, 32, 1, 16, 32
+        ];
+        distinct_names[0] = self.distinct_names as u8;
+        hide_results_until_finish[0] = self.hide_results_until_finish as u8;
+        lock_options_on_first_vote[0] = self.lock_options_on_first_vote as u8;
+        pack_u64(self.min_voter_lamports, min_voter_lamports);
+        pack_u32(self.min_quorum, min_quorum);
+        weight_by_lamports[0] = self.weight_by_lamports as u8;
+        sealed_after_finish[0] = self.sealed_after_finish as u8;
+        max_voters_per_option[0] = self.max_voters_per_option;
+        *fee_bps = self.fee_bps.to_le_bytes();
+        fee_destination.copy_from_slice(self.fee_destination.as_ref());
+        max_options[0] = self.max_options;
+        require_cosigner[0] = self.require_cosigner as u8;
+        cosigner.copy_from_slice(self.cosigner.as_ref());
+        pack_i64(self.deadline, deadline);
+        tally_mode[0] = self.tally_mode as u8;
+        auto_finish_on_quorum[0] = self.auto_finish_on_quorum as u8;
+        pack_i64(self.min_action_interval, min_action_interval);
+        allow_vote_changes[0] = self.allow_vote_changes as u8;
+        oracle_weighted[0] = self.oracle_weighted as u8;
+        oracle_authority.copy_from_slice(self.oracle_authority.as_ref());
+        min_options[0] = self.min_options;
+        *salt = self.salt;
+        token_mint.copy_from_slice(self.token_mint.as_ref());
+    }
+
+    /// Whether options are ranked by their accumulated weight rather than their vote count.
+    pub fn is_weighted(&self) -> bool {
+        self.weight_by_lamports || self.tally_mode != TallyMode::Standard || self.oracle_weighted
+    }
+
+    /// Splits a closed topic's lamports into the fee and the refund.
+    pub fn fee_split(&self, lamports: u64) -> (u64, u64) {
+        let fee = (lamports as u128 * self.fee_bps as u128 / 10_000) as u64;
+        (fee, lamports - fee)
+    }
+
+    /// Rejects settings outside what the topic layout can hold.
+    pub fn validate(&self) -> Result<(), ProgramError> {
+        if self.max_voters_per_option as usize > MAX_VOTERS {
+            return Err(ProgramError::InvalidArgument);
+        }
+        if self.fee_bps > 10_000 {
+            return Err(ProgramError::InvalidArgument);
+        }
+        if self.max_options as usize > MAX_OPTIONS {
+            return Err(ProgramError::InvalidArgument);
+        }
+        if self.min_options as usize > MAX_OPTIONS
+            || (self.max_options != 0 && self.min_options > self.max_options)
+        {
+            return Err(ProgramError::InvalidArgument);
+        }
+        if self.require_cosigner && self.cosigner == Pubkey::default() {
+            return Err(ProgramError::InvalidArgument);
+        }
+        if self.weight_by_lamports && self.tally_mode != TallyMode::Standard {
+            return Err(ProgramError::InvalidArgument);
+        }
+        if self.auto_finish_on_quorum && self.min_quorum == 0 {
+            return Err(ProgramError::InvalidArgument);
+        }
+        if self.min_action_interval < 0 {
+            return Err(ProgramError::InvalidArgument);
+        }
+        if self.oracle_weighted
+            && (self.oracle_authority == Pubkey::default()
+                || self.weight_by_lamports
+                || self.tally_mode != TallyMode::Standard)
+        {
+            return Err(ProgramError::InvalidArgument);
+        }
+        Ok(())
+    }
+
+    pub fn get_packed_len() -> usize {
+        CONFIG_LEN
+    }
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Option {
+    belongs_to: Pubkey,
+    belongs_idx: u8,
+    name: [u8; 100],
+    voters: [Pubkey; MAX_VOTERS],
+    current_voter_index: u8,
+    anon_votes: u8,
+    vote_times: [i64; MAX_VOTERS],
+    weight: u64,
+    /// Token account each voter voted with, the default key for plain votes.
+    token_accounts: [Pubkey; MAX_VOTERS],
+}
+
+/// Raw field access for tests outside the crate.
+#[cfg(feature = "test-utils")]
+impl Option {
+    pub fn belongs_to(&self) -> &Pubkey {
+        &self.belongs_to
+    }
+
+    pub fn belongs_idx(&self) -> u8 {
+        self.belongs_idx
+    }
+
+    pub fn name(&self) -> &[u8; 100] {
+        &self.name
+    }
+
+    pub fn voters(&self) -> &[Pubkey; MAX_VOTERS] {
+        &self.voters
+    }
+
+    pub fn current_voter_index(&self) -> u8 {
+        self.current_voter_index
+    }
+
+    pub fn anon_votes(&self) -> u8 {
+        self.anon_votes
+    }
+
+    pub fn vote_times(&self) -> &[i64; MAX_VOTERS] {
+        &self.vote_times
+    }
+
+    pub fn weight(&self) -> u64 {
+        self.weight
+    }
+
+    pub fn token_accounts(&self) -> &[Pubkey; MAX_VOTERS] {
+        &self.token_accounts
+    }
+}
+
+impl Default for Option {
+    fn default() -> Self {
+        Option {
+            belongs_to: Pubkey::default(),
+            belongs_idx: 0,
+            name: [0; 100],
+            voters: [Pubkey::default(); MAX_VOTERS],
+            current_voter_index: 0,
+            anon_votes: 0,
+            vote_times: [0; MAX_VOTERS],
+            weight: 0,
+            token_accounts: [Pubkey::default(); MAX_VOTERS],
+        }
+    }
+}
+
+impl Option {
+    pub fn new(belongs_to: &Pubkey, belongs_idx: u8, name: &str) -> Result<Option, ProgramError> {
+        str_check(name)?;
+        let mut name_data = [0; 100];
+        str_pack(name, &mut name_data);
+        Ok(Option {
+            belongs_to: *belongs_to,
+            belongs_idx,
+            name: name_data,
+            ..Option::default()
+        })
+    }
+
+    /// Records a voter, failing with `OptionFull` once `max_voters` is reached (0 for no limit).
+    pub fn add_voter(
+        &mut self,
+        voter: &Pubkey,
+        now: i64,
+        max_voters: u8,
+    ) -> Result<(), ProgramError> {
+        if self.current_voter_index as usize == self.voters.len() {
+            return Err(ProgramError::InvalidArgument);
+        }
+        if max_voters != 0 && self.current_voter_index >= max_voters {
+            return Err(TopicError::OptionFull.into());
+        }
+
+        self.voters[self.current_voter_index as usize] = *voter;
+        self.vote_times[self.current_voter_index as usize] = now;
+        self.current_voter_index += 1;
+        Ok(())
+    }
+
+    /// Drops `voter` from the option, keeping the remaining voters in the order they voted.
+    pub fn remove_voter(&mut self, voter: &Pubkey) -> Result<(), ProgramError> {
+        let pos = self
+            .active_voters()
+            .iter()
+            .position(|v| v == voter)
+            .ok_or(ProgramError::InvalidArgument)?;
+        let end = self.current_voter_index as usize;
+        self.voters.copy_within(pos + 1..end, pos);
+        self.vote_times.copy_within(pos + 1..end, pos);
+        self.token_accounts.copy_within(pos + 1..end, pos);
+        self.voters[end - 1] = Pubkey::default();
+        self.vote_times[end - 1] = 0;
+        self.token_accounts[end - 1] = Pubkey::default();
+        self.current_voter_index -= 1;
+        Ok(())
+    }
+
+    pub fn set_name(&mut self, name: &str) -> Result<(), ProgramError> {
+        str_check(name)?;
+        let mut name_data = [0; 100];
+        str_pack(name, &mut name_data);
+        self.name = name_data;
+        Ok(())
+    }
+
+    pub fn add_weight(&mut self, weight: u64) -> Result<(), ProgramError> {
+        self.weight = self
+            .weight
+            .checked_add(weight)
+            .ok_or(ProgramError::ArithmeticOverflow)?;
+        Ok(())
+    }
+
+    /// An option slot that was never initialized by `Topic::add_option`.
+    pub fn is_empty(&self) -> bool {
+        matches!(str_unpack(&self.name), Ok("")) && self.belongs_to == Pubkey::default()
+    }
+
+    pub fn vote_count(&self) -> u32 {
+        self.current_voter_index as u32 + self.anon_votes as u32
+    }
+
+    pub fn active_voters(&self) -> &[Pubkey] {
+        &self.voters[..self.current_voter_index as usize]
+    }
+
+    pub fn active_vote_times(&self) -> &[i64] {
+        &self.vote_times[..self.current_voter_index as usize]
+    }
+
+    pub fn active_token_accounts(&self) -> &[Pubkey] {
+        &self.token_accounts[..self.current_voter_index as usize]
+    }
+
+    pub fn add_anon_vote(&mut self) -> Result<(), ProgramError> {
+        self.anon_votes = self
+            .anon_votes
+            .checked_add(1)
+            .ok_or(ProgramError::InvalidArgument)?;
+        Ok(())
+    }
+
+    pub fn unpack_from_slice(src: &[u8]) -> Result<Self, ProgramError> {
+        let src = array_ref!(src, 0, OPTION_LEN);
+        let (
+            belongs_to,
+            belongs_idx,
+            name,
+            voters_bytes,
+            current_voter_index,
+            anon_votes,
+            times_bytes,
+            weight,
+            token_accounts_bytes,
+        ) = array_refs![
+            src,
+            32,
+            1,
+            MAX_OPTION_NAME,
+            32 * MAX_VOTERS,
+            1,
+            1,
+            8 * MAX_VOTERS,
+            8,
+            32 * MAX_VOTERS
+        ];
+        let belongs_to = Pubkey::new_from_array(*belongs_to);
+        let belongs_idx = belongs_idx[0];
+        let mut voters = [Pubkey::default(); MAX_VOTERS];
+        for (i, voter) in voters.iter_mut().enumerate() {
+            *voter = Pubkey::new_from_array(*array_ref!(voters_bytes, i * 32, 32));
+        }
+        let current_voter_index = current_voter_index[0];
+        if current_voter_index as usize > MAX_VOTERS {
+            return Err(ProgramError::InvalidAccountData);
+        }
+        let anon_votes = anon_votes[0];
+        let mut vote_times = [0; MAX_VOTERS];
+        for (i, time) in vote_times.iter_mut().enumerate() {
+            *time = unpack_i64(array_ref!(times_bytes, i * 8, 8));
+        }
+        let weight = unpack_u64(weight);
+        let mut token_accounts = [Pubkey::default(); MAX_VOTERS];
+        for (i, token_account) in token_accounts.iter_mut().enumerate() {
+            *token_account = Pubkey::new_from_array(*array_ref!(token_accounts_bytes, i * 32, 32));
+        }
+
+        Ok(Option {
+            belongs_to,
+            belongs_idx,
+            name: *name,
+            voters,
+            current_voter_index,
+            anon_votes,
+            vote_times,
+            weight,
+            token_accounts,
+        })
+    }
+
+    pub fn pack_into_slice(&self, dst: &mut [u8]) {
+        let src = array_mut_ref![dst, 0, OPTION_LEN];
+        let (
+            belongs_to,
+            belongs_idx,
+            name,
+            voters_bytes,
+            current_voter_index,
+            anon_votes,
+            times_bytes,
+            weight,
+            token_accounts_bytes,
+        ) = mut_array_refs![
+            src,
+            32,
+            1,
+            MAX_OPTION_NAME,
+            32 * MAX_VOTERS,
+            1,
+            1,
+            8 * MAX_VOTERS,
+            8,
+            32 * MAX_VOTERS
+        ];
+        belongs_to.copy_from_slice(&self.belongs_to.to_bytes());
+        name.copy_from_slice(&self.name);
+        belongs_idx[0] = self.belongs_idx;
+        for (i, pubkey) in self.voters.iter().enumerate() {
+            let (start, end) = (i * 32, i * 32 + 32);
+            voters_bytes[start..end].copy_from_slice(&pubkey.to_bytes());
+        }
+        current_voter_index[0] = self.current_voter_index;
+        anon_votes[0] = self.anon_votes;
+        for (i, time) in self.vote_times.iter().enumerate() {
+            pack_i64(*time, array_mut_ref!(times_bytes, i * 8, 8));
+        }
+        pack_u64(self.weight, weight);
+        for (i, token_account) in self.token_accounts.iter().enumerate() {
+            token_accounts_bytes[i * 32..i * 32 + 32].copy_from_slice(token_account.as_ref());
+        }
+    }
+
+    pub fn empty_bytes() -> [u8; OPTION_LEN] {
+        [0; OPTION_LEN]
+    }
+
+    pub fn get_packed_len() -> usize {
+        OPTION_LEN
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        error::TopicError,
+        state::{
+            OptIdx, Option, OracleWeight, TallyMode, TokenAccountData, Topic, TopicConfig,
+            TopicState, CONFIG_LEN, MAX_BANNED, MAX_NULLIFIERS, MAX_OPTIONS, MAX_SEEN_NONCES,
+            MAX_TOPIC_NAME, MAX_VOTERS, MAX_WHITELIST, NOTE_LEN, OPEN_VOTING, OPTION_LEN,
+        },
+    };
+    use solana_program::{account_info::AccountInfo, program_error::ProgramError, pubkey::Pubkey};
+
+    #[test]
+    fn test_option_pack_unpack() {
+        let pkey = Pubkey::default();
+        let mut opt = Option::new(&pkey, 0, "test_option").unwrap();
+        let mut data = Option::empty_bytes();
+        opt.add_voter(&Pubkey::new_unique(), 1_600_000_000, 0)
+            .unwrap();
+        opt.pack_into_slice(&mut data[..]);
+
+        let opt2 = Option::unpack_from_slice(&data).unwrap();
+        assert_eq!(opt, opt2);
+    }
+
+    #[test]
+    fn test_topic_pack_unpack() {
+        let pk = Pubkey::new_unique();
+        let mut topic = Topic::new("test_topic", &pk);
+        topic.add_option(&pk, "option_name").unwrap();
+        topic.category = [3; 32];
+        let mut data = Topic::empty_bytes();
+        topic.pack_into_slice(&mut data).unwrap();
+
+        let topic2 = Topic::unpack_from_slice(&data).unwrap();
         assert_eq!(topic, topic2);
+        assert_eq!([3; 32], topic2.category);
+    }
+
+    #[test]
+    fn test_topic_anon_vote() {
+        let pk = Pubkey::new_unique();
+        let mut topic = Topic::new("test_topic", &pk);
+        topic.add_option(&pk, "option_name").unwrap();
+        let nullifier = [7; 32];
+        topic.anon_vote(OptIdx(0), &nullifier).unwrap();
+        assert!(topic.has_nullifier(&nullifier));
+        assert_eq!(1, topic.options[0].anon_votes);
+        assert_eq!(
+            Err(ProgramError::InvalidArgument),
+            topic.anon_vote(OptIdx(0), &nullifier)
+        );
+
+        let mut data = Topic::empty_bytes();
+        topic.pack_into_slice(&mut data).unwrap();
+        let topic2 = Topic::unpack_from_slice(&data).unwrap();
+        assert_eq!(topic, topic2);
+    }
+
+    #[test]
+    fn test_topic_from_account_info() {
+        let program_id = Pubkey::new_unique();
+        let key = Pubkey::new_unique();
+        let topic = Topic::new("test_topic", &key);
+        let mut data = Topic::empty_bytes();
+        topic.pack_into_slice(&mut data).unwrap();
+        let mut lamports = 0;
+        let acc = AccountInfo::new(
+            &key,
+            false,
+            true,
+            &mut lamports,
+            &mut data,
+            &program_id,
+            false,
+            0,
+        );
+        assert_eq!(Ok(topic), Topic::from_account_info(&acc, &program_id));
+
+        assert_eq!(
+            Err(TopicError::WrongProgramOwner.into()),
+            Topic::from_account_info(&acc, &Pubkey::new_unique())
+        );
+    }
+
+    #[test]
+    fn test_topic_save() {
+        let program_id = Pubkey::new_unique();
+        let key = Pubkey::new_unique();
+        let mut topic = Topic::new("test_topic", &key);
+        topic.add_option(&key, "option_name").unwrap();
+        let mut data = Topic::empty_bytes();
+        let mut lamports = 0;
+        let acc = AccountInfo::new(
+            &key,
+            false,
+            true,
+            &mut lamports,
+            &mut data,
+            &program_id,
+            false,
+            0,
+        );
+        topic.save(&acc).unwrap();
+        assert_eq!(
+            Ok(topic.clone()),
+            Topic::from_account_info(&acc, &program_id)
+        );
+
+        let mut short_data = [0; 100];
+        let mut lamports = 0;
+        let acc = AccountInfo::new(
+            &key,
+            false,
+            true,
+            &mut lamports,
+            &mut short_data,
+            &program_id,
+            false,
+            0,
+        );
+        assert_eq!(Err(ProgramError::InvalidAccountData), topic.save(&acc));
+    }
+
+    #[test]
+    fn test_topic_voter_times() {
+        let pk = Pubkey::new_unique();
+        let mut topic = Topic::new("test_topic", &pk);
+        topic.add_option(&pk, "option_name").unwrap();
+        topic.add_option(&pk, "option_name2").unwrap();
+        assert_eq!(None, topic.first_voter_time());
+        assert_eq!(None, topic.last_voter_time());
+
+        topic.vote(OptIdx(1), &Pubkey::new_unique(), 300).unwrap();
+        topic.vote(OptIdx(0), &Pubkey::new_unique(), 100).unwrap();
+        topic.vote(OptIdx(1), &Pubkey::new_unique(), 500).unwrap();
+        topic.vote(OptIdx(0), &Pubkey::new_unique(), 200).unwrap();
+        assert_eq!(Some(100), topic.first_voter_time());
+        assert_eq!(Some(500), topic.last_voter_time());
+    }
+
+    #[test]
+    fn test_topic_distinct_names() {
+        let pk = Pubkey::new_unique();
+        let mut topic = Topic::new("pizza", &pk);
+        topic.add_option(&pk, "pizza").unwrap();
+
+        let mut topic = Topic::new("pizza", &pk);
+        topic.config = TopicConfig {
+            distinct_names: true,
+            ..TopicConfig::default()
+        };
+        assert_eq!(
+            Err(ProgramError::InvalidArgument),
+            topic.add_option(&pk, "pizza")
+        );
+        topic.add_option(&pk, "pasta").unwrap();
+
+        let mut data = Topic::empty_bytes();
+        topic.pack_into_slice(&mut data).unwrap();
+        assert_eq!(Ok(topic), Topic::unpack_from_slice(&data));
+    }
+
+    #[test]
+    fn test_topic_full() {
+        let pk = Pubkey::new_unique();
+        let mut topic = Topic::new("test_topic", &pk);
+        for i in 0..10 {
+            topic.add_option(&pk, &format!("option_{}", i)).unwrap();
+        }
+        assert_eq!(
+            Err(TopicError::TopicFull.into()),
+            topic.add_option(&pk, "option_10")
+        );
+    }
+
+    #[test]
+    fn test_option_is_empty() {
+        let pk = Pubkey::new_unique();
+        assert!(Option::default().is_empty());
+        assert!(!Option::new(&pk, 0, "option_name").unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_topic_finalize_skips_empty_options() {
+        let pk = Pubkey::new_unique();
+        let mut topic = Topic::new("test_topic", &pk);
+        topic.add_option(&pk, "option_name").unwrap();
+        topic.add_option(&pk, "option_name2").unwrap();
+        topic.add_option(&pk, "option_name3").unwrap();
+        topic.vote(OptIdx(2), &Pubkey::new_unique(), 0).unwrap();
+        topic.options[0] = Option::default();
+        topic.opt_current_idx = 1;
+        topic.finalize().unwrap();
+        assert!(topic.is_finished);
+        assert_eq!(2, topic.result_idx);
+    }
+
+    #[test]
+    fn test_topic_vote_percentages() {
+        let pk = Pubkey::new_unique();
+        let mut topic = Topic::new("test_topic", &pk);
+        topic.add_option(&pk, "option_name").unwrap();
+        topic.add_option(&pk, "option_name2").unwrap();
+        topic.add_option(&pk, "option_name3").unwrap();
+        assert_eq!(vec![(0, 0.0), (1, 0.0), (2, 0.0)], topic.vote_percentages());
+
+        topic.vote(OptIdx(0), &Pubkey::new_unique(), 0).unwrap();
+        topic.vote(OptIdx(2), &Pubkey::new_unique(), 0).unwrap();
+        topic.vote(OptIdx(2), &Pubkey::new_unique(), 0).unwrap();
+        let percentages = topic.vote_percentages();
+        assert_eq!(3, percentages.len());
+        assert!((percentages[0].1 - 1.0 / 3.0).abs() < f32::EPSILON);
+        assert_eq!((1, 0.0), percentages[1]);
+        let sum: f32 = percentages.iter().map(|(_, share)| share).sum();
+        assert!((sum - 1.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_topic_numeric_layout() {
+        let pk = Pubkey::new_unique();
+        let mut topic = Topic::new("test_topic", &pk);
+        topic.add_option(&pk, "option_name").unwrap();
+        topic.add_option(&pk, "option_name2").unwrap();
+        topic
+            .vote(OptIdx(1), &Pubkey::new_unique(), 0x1122_3344_5566_7788)
+            .unwrap();
+        topic.anon_vote(OptIdx(1), &[5; 32]).unwrap();
+        topic.finalize().unwrap();
+        topic.config.min_voter_lamports = 0x0102_0304_0506_0708;
+        let mut data = Topic::empty_bytes();
+        topic.pack_into_slice(&mut data).unwrap();
+
+        // options start after the 100 byte name, each option is 2303 bytes
+        let opt1 = 100 + 2303;
+        assert_eq!(1, data[opt1 + 32]);
+        assert_eq!(1, data[opt1 + 1093]);
+        assert_eq!(1, data[opt1 + 1094]);
+        assert_eq!(
+            [0x88, 0x77, 0x66, 0x55, 0x44, 0x33, 0x22, 0x11],
+            data[opt1 + 1095..opt1 + 1103]
+        );
+        assert_eq!(2, data[23130]);
+        assert_eq!(pk.to_bytes(), data[23131..23163]);
+        assert_eq!(1, data[23163]);
+        assert_eq!(1, data[23164]);
+        assert_eq!(1, data[24125]);
+        assert_eq!(
+            [0x08, 0x07, 0x06, 0x05, 0x04, 0x03, 0x02, 0x01],
+            data[24161..24169]
+        );
+    }
+
+    #[test]
+    fn test_topic_reset() {
+        let pk = Pubkey::new_unique();
+        let mut topic = Topic::new("test_topic", &pk);
+        topic.add_option(&pk, "option_name").unwrap();
+        topic.vote(OptIdx(0), &Pubkey::new_unique(), 0).unwrap();
+        topic.anon_vote(OptIdx(0), &[1; 32]).unwrap();
+        topic.is_paused = true;
+        topic.reset();
+        let mut expect = Topic::new("test_topic", &pk);
+        expect.options_ever_added = 1;
+        expect.is_paused = true;
+        assert_eq!(expect, topic);
+        assert!(topic.is_paused);
+    }
+
+    #[test]
+    fn test_topic_vote_out_of_range() {
+        let pk = Pubkey::new_unique();
+        let mut topic = Topic::new("test_topic", &pk);
+        topic.add_option(&pk, "option_name").unwrap();
+        topic.add_option(&pk, "option_name2").unwrap();
+        assert_eq!(
+            Err(ProgramError::InvalidArgument),
+            topic.vote(OptIdx(2), &Pubkey::new_unique(), 0)
+        );
+
+        topic.opt_current_idx = 1;
+        assert_eq!(
+            Err(ProgramError::InvalidArgument),
+            topic.vote(OptIdx(1), &Pubkey::new_unique(), 0)
+        );
+
+        topic.opt_current_idx = 3;
+        assert_eq!(
+            Err(ProgramError::InvalidArgument),
+            topic.vote(OptIdx(2), &Pubkey::new_unique(), 0)
+        );
+        assert_eq!(
+            Err(ProgramError::InvalidArgument),
+            topic.anon_vote(OptIdx(2), &[1; 32])
+        );
+    }
+
+    #[test]
+    fn test_topic_reorder_options() {
+        let pk = Pubkey::new_unique();
+        let mut topic = Topic::new("test_topic", &pk);
+        topic.add_option(&pk, "a").unwrap();
+        topic.add_option(&pk, "b").unwrap();
+        topic.add_option(&pk, "c").unwrap();
+
+        let mut expect = Topic::new("test_topic", &pk);
+        expect.add_option(&pk, "c").unwrap();
+        expect.add_option(&pk, "a").unwrap();
+        expect.add_option(&pk, "b").unwrap();
+        for (opt, belongs_idx) in expect.options.iter_mut().zip([2, 0, 1]) {
+            opt.belongs_idx = belongs_idx;
+        }
+        topic
+            .reorder_options(&[2, 0, 1, 3, 4, 5, 6, 7, 8, 9])
+            .unwrap();
+        assert_eq!(expect, topic);
+
+        for order in [
+            [0, 0, 1, 3, 4, 5, 6, 7, 8, 9],
+            [0, 1, 3, 2, 4, 5, 6, 7, 8, 9],
+            [0, 1, 2, 3, 4, 5, 6, 7, 9, 8],
+            [0, 1, 10, 3, 4, 5, 6, 7, 8, 9],
+        ] {
+            assert_eq!(
+                Err(ProgramError::InvalidArgument),
+                topic.reorder_options(&order)
+            );
+        }
+
+        topic.vote(OptIdx(0), &pk, 0).unwrap();
+        assert_eq!(
+            Err(TopicError::OptionsLocked.into()),
+            topic.reorder_options(&[1, 0, 2, 3, 4, 5, 6, 7, 8, 9])
+        );
+    }
+
+    #[test]
+    fn test_topic_needs_tiebreak() {
+        let pk = Pubkey::new_unique();
+        let mut topic = Topic::new("test_topic", &pk);
+        topic.add_option(&pk, "option_name").unwrap();
+        assert!(!topic.needs_tiebreak());
+
+        topic.add_option(&pk, "option_name2").unwrap();
+        topic.add_option(&pk, "option_name3").unwrap();
+        topic.vote(OptIdx(1), &Pubkey::new_unique(), 0).unwrap();
+        assert!(!topic.needs_tiebreak());
+
+        topic.vote(OptIdx(2), &Pubkey::new_unique(), 0).unwrap();
+        assert!(topic.needs_tiebreak());
+
+        topic.vote(OptIdx(2), &Pubkey::new_unique(), 0).unwrap();
+        assert!(!topic.needs_tiebreak());
+    }
+
+    /// Non-zero regions of a known-good packed topic, as (offset, bytes).
+    const GOLDEN_TOPIC: &[(usize, &[u8])] = &[
+        (0, b"golden|"),
+        (100, &[1; 32]),
+        (133, b"yes|"),
+        (233, &[2; 32]),
+        (1193, &[1]),
+        (1195, &[0x10, 0x0e, 0, 0, 0, 0, 0, 0]),
+        (2403, &[1; 32]),
+        (2435, &[1]),
+        (2436, b"no|"),
+        (23130, &[2]),
+        (23131, &[3; 32]),
+        (23164, &[1]),
+        (24126, &[4; 32]),
+        (24158, &[1]),
+        (24169, &[1, 0, 0, 0]),
+        (24262, &[1]),
+        (24345, b"certified"),
+    ];
+
+    #[test]
+    fn test_topic_golden_bytes() {
+        let mut golden = vec![0; Topic::get_packed_len()];
+        for (offset, bytes) in GOLDEN_TOPIC {
+            golden[*offset..*offset + bytes.len()].copy_from_slice(bytes);
+        }
+
+        let topic_key = Pubkey::new_from_array([1; 32]);
+        let mut expect = Topic::new("golden", &Pubkey::new_from_array([3; 32]));
+        expect.options[0] = Option::new(&topic_key, 0, "yes").unwrap();
+        expect.options[0]
+            .add_voter(&Pubkey::new_from_array([2; 32]), 3600, 0)
+            .unwrap();
+        expect.options[1] = Option::new(&topic_key, 1, "no").unwrap();
+        expect.opt_current_idx = 2;
+        expect.is_finished = true;
+        expect.category = [4; 32];
+        expect.config.distinct_names = true;
+        expect.config.min_quorum = 1;
+        expect.note[..9].copy_from_slice(b"certified");
+
+        let topic = Topic::unpack_from_slice(&golden).unwrap();
+        assert_eq!(expect, topic);
+
+        let mut data = Topic::empty_bytes();
+        topic.pack_into_slice(&mut data).unwrap();
+        assert_eq!(golden, data);
+    }
+
+    #[test]
+    fn test_topic_options_iter() {
+        let pk = Pubkey::new_unique();
+        let mut topic = Topic::new("test_topic", &pk);
+        assert_eq!(0, topic.options_iter().count());
+
+        for name in ["a", "b", "c"] {
+            topic.add_option(&pk, name).unwrap();
+        }
+        let indices: Vec<u8> = topic.options_iter().map(|(i, _)| i).collect();
+        assert_eq!(topic.opt_current_idx as usize, indices.len());
+        assert_eq!(vec![0, 1, 2], indices);
+        for (i, opt) in topic.options_iter() {
+            assert_eq!(&topic.options[i as usize], opt);
+        }
+    }
+
+    #[test]
+    fn test_topic_winning_margin_percent() {
+        let pk = Pubkey::new_unique();
+        let mut topic = Topic::new("test_topic", &pk);
+        topic.add_option(&pk, "option_name").unwrap();
+        topic.add_option(&pk, "option_name2").unwrap();
+        assert_eq!(0.0, topic.winning_margin_percent());
+
+        for _ in 0..9 {
+            topic.vote(OptIdx(0), &Pubkey::new_unique(), 0).unwrap();
+        }
+        topic.vote(OptIdx(1), &Pubkey::new_unique(), 0).unwrap();
+        assert!((topic.winning_margin_percent() - 80.0).abs() < 1e-4);
+
+        for _ in 0..7 {
+            topic.vote(OptIdx(1), &Pubkey::new_unique(), 0).unwrap();
+        }
+        assert!((topic.winning_margin_percent() - 100.0 / 17.0).abs() < 1e-4);
+    }
+
+    #[test]
+    fn test_topic_rename_option() {
+        let pk = Pubkey::new_unique();
+        let mut topic = Topic::new("test_topic", &pk);
+        topic.add_option(&pk, "pizaz").unwrap();
+        topic.add_option(&pk, "pasta").unwrap();
+        topic.rename_option(OptIdx(0), "pizza").unwrap();
+        assert_eq!(Option::new(&pk, 0, "pizza").unwrap(), topic.options[0]);
+
+        topic.vote(OptIdx(1), &pk, 0).unwrap();
+        assert_eq!(
+            Err(TopicError::OptionHasVotes.into()),
+            topic.rename_option(OptIdx(1), "pesto")
+        );
+        assert_eq!(
+            Err(ProgramError::InvalidArgument),
+            topic.rename_option(OptIdx(2), "salad")
+        );
+    }
+
+    #[test]
+    fn test_topic_is_owner() {
+        let owner = Pubkey::new_unique();
+        let topic = Topic::new("test_topic", &owner);
+        assert!(topic.is_owner(&owner));
+        assert!(!topic.is_owner(&Pubkey::new_unique()));
+    }
+
+    #[test]
+    fn test_topic_pack_short_buffer() {
+        let topic = Topic::new("test_topic", &Pubkey::new_unique());
+        let mut data = vec![0; Topic::get_packed_len() - 1];
+        assert_eq!(
+            Err(ProgramError::InvalidAccountData),
+            topic.pack_into_slice(&mut data)
+        );
+        assert!(data.iter().all(|&b| b == 0));
+        assert_eq!(
+            Err(ProgramError::InvalidAccountData),
+            Topic::unpack_from_slice(&data)
+        );
+        assert_eq!(
+            Err(ProgramError::InvalidAccountData),
+            Topic::unpack_from_slice(&[])
+        );
+    }
+
+    #[test]
+    fn test_opt_idx_new() {
+        let pk = Pubkey::new_unique();
+        let mut topic = Topic::new("test_topic", &pk);
+        assert_eq!(Err(ProgramError::InvalidArgument), OptIdx::new(0, &topic));
+
+        topic.add_option(&pk, "option_name").unwrap();
+        topic.add_option(&pk, "option_name2").unwrap();
+        assert_eq!(Ok(OptIdx(1)), OptIdx::new(1, &topic));
+        assert_eq!(1, u8::from(OptIdx::new(1, &topic).unwrap()));
+        assert_eq!(Err(ProgramError::InvalidArgument), OptIdx::new(2, &topic));
+
+        topic.options[1] = Option::default();
+        assert_eq!(Err(ProgramError::InvalidArgument), OptIdx::new(1, &topic));
+    }
+
+    #[test]
+    fn test_topic_describe_result() {
+        let pk = Pubkey::new_unique();
+        let mut topic = Topic::new("test_topic", &pk);
+        topic.add_option(&pk, "Pasta").unwrap();
+        topic.add_option(&pk, "Pizza").unwrap();
+        for i in 0..20 {
+            let opt_idx = if i < 12 { 1 } else { 0 };
+            topic
+                .vote(OptIdx(opt_idx), &Pubkey::new_unique(), 0)
+                .unwrap();
+        }
+        assert_eq!(
+            Err(ProgramError::InvalidAccountData),
+            topic.describe_result()
+        );
+
+        topic.finalize().unwrap();
+        assert_eq!(
+            Ok("'Pizza' won with 12 of 20 votes (60%)".to_string()),
+            topic.describe_result()
+        );
+
+        topic.result_idx = MAX_OPTIONS as u8;
+        assert_eq!(
+            Err(ProgramError::InvalidAccountData),
+            topic.describe_result()
+        );
+    }
+
+    #[test]
+    fn test_topic_describe_weighted_result() {
+        let pk = Pubkey::new_unique();
+        let mut topic = Topic::new("test_topic", &pk);
+        topic.config.tally_mode = TallyMode::Quadratic;
+        topic.add_option(&pk, "Pasta").unwrap();
+        topic.add_option(&pk, "Pizza").unwrap();
+        for (opt_idx, weight) in [(0, 1), (0, 1), (1, 6)] {
+            topic
+                .vote(OptIdx(opt_idx), &Pubkey::new_unique(), 0)
+                .unwrap();
+            topic.options[opt_idx as usize].add_weight(weight).unwrap();
+        }
+        topic.finalize().unwrap();
+        assert_eq!(
+            Ok("'Pizza' won with 6 of 8 weight (75%)".to_string()),
+            topic.describe_result()
+        );
+    }
+
+    #[test]
+    fn test_topic_packed_len() {
+        let pk = Pubkey::new_unique();
+        let topic = Topic::new("test_topic", &pk);
+        let mut data = Topic::empty_bytes();
+        topic.pack_into_slice(&mut data).unwrap();
+
+        assert_eq!(Topic::get_packed_len(), Topic::empty_bytes().len());
+        assert!(Topic::get_packed_len() >= 11075);
+        assert_eq!(
+            Topic::get_packed_len(),
+            MAX_TOPIC_NAME
+                + OPTION_LEN * MAX_OPTIONS
+                + 1
+                + 32
+                + 1
+                + 1
+                + 32 * MAX_NULLIFIERS
+                + 1
+                + 32
+                + CONFIG_LEN
+                + NOTE_LEN
+                + 32 * MAX_BANNED
+                + 1
+                + 4
+                + 1
+                + 32 * MAX_WHITELIST
+                + 1
+                + 40 * MAX_SEEN_NONCES
+                + 1
+                + 8 * MAX_WHITELIST
+                + 8
+                + 2
+                + 1
+        );
+    }
+
+    #[test]
+    fn test_topic_max_voters_per_option() {
+        let pk = Pubkey::new_unique();
+        let mut topic = Topic::new("test_topic", &pk);
+        topic.config.max_voters_per_option = 2;
+        topic.add_option(&pk, "slot_a").unwrap();
+        topic.add_option(&pk, "slot_b").unwrap();
+        topic.vote(OptIdx(0), &Pubkey::new_unique(), 0).unwrap();
+        topic.vote(OptIdx(0), &Pubkey::new_unique(), 0).unwrap();
+        assert_eq!(
+            Err(TopicError::OptionFull.into()),
+            topic.vote(OptIdx(0), &Pubkey::new_unique(), 0)
+        );
+        topic.vote(OptIdx(1), &Pubkey::new_unique(), 0).unwrap();
+
+        assert_eq!(Ok(()), topic.config.validate());
+        topic.config.max_voters_per_option = 31;
+        assert_eq!(Err(ProgramError::InvalidArgument), topic.config.validate());
+    }
+
+    #[test]
+    fn test_topic_fill_option_to_max_voters() {
+        let pk = Pubkey::new_unique();
+        for max_voters in [MAX_VOTERS as u8, 0] {
+            let mut topic = Topic::new("test_topic", &pk);
+            topic.config.max_voters_per_option = max_voters;
+            topic.config.validate().unwrap();
+            topic.add_option(&pk, "option_name").unwrap();
+            for _ in 0..MAX_VOTERS {
+                topic.vote(OptIdx(0), &Pubkey::new_unique(), 0).unwrap();
+            }
+            assert_eq!(MAX_VOTERS as u32, topic.options[0].vote_count());
+            assert!(topic.vote(OptIdx(0), &Pubkey::new_unique(), 0).is_err());
+
+            let mut data = Topic::empty_bytes();
+            topic.pack_into_slice(&mut data).unwrap();
+            assert_eq!(Ok(topic), Topic::unpack_from_slice(&data));
+        }
+    }
+
+    #[test]
+    fn test_topic_quorum_progress() {
+        let pk = Pubkey::new_unique();
+        let mut topic = Topic::new("test_topic", &pk);
+        topic.add_option(&pk, "option_name").unwrap();
+        assert_eq!(1.0, topic.quorum_progress());
+
+        topic.config.min_quorum = 4;
+        assert_eq!(0.0, topic.quorum_progress());
+        topic.vote(OptIdx(0), &Pubkey::new_unique(), 0).unwrap();
+        assert_eq!(0.25, topic.quorum_progress());
+        for _ in 0..4 {
+            topic.vote(OptIdx(0), &Pubkey::new_unique(), 0).unwrap();
+        }
+        assert_eq!(1.0, topic.quorum_progress());
+    }
+
+    #[test]
+    fn test_topic_ban_voter() {
+        let pk = Pubkey::new_unique();
+        let banned = Pubkey::new_unique();
+        let mut topic = Topic::new("test_topic", &pk);
+        assert!(!topic.is_banned(&banned));
+        topic.ban_voter(&banned).unwrap();
+        topic.ban_voter(&banned).unwrap();
+        assert!(topic.is_banned(&banned));
+        assert!(!topic.is_banned(&pk));
+        assert_eq!(1, topic.banned_count);
+
+        for _ in 1..MAX_BANNED {
+            topic.ban_voter(&Pubkey::new_unique()).unwrap();
+        }
+        assert_eq!(
+            Err(ProgramError::InvalidArgument),
+            topic.ban_voter(&Pubkey::new_unique())
+        );
+
+        let mut data = Topic::empty_bytes();
+        topic.pack_into_slice(&mut data).unwrap();
+        assert_eq!(Ok(topic), Topic::unpack_from_slice(&data));
+    }
+
+    #[test]
+    fn test_topic_config_fee_split() {
+        let mut config = TopicConfig::default();
+        assert_eq!((0, 10_000), config.fee_split(10_000));
+        config.fee_bps = 250;
+        assert_eq!((250, 9_750), config.fee_split(10_000));
+        assert_eq!((0, 39), config.fee_split(39));
+        config.fee_bps = 3_333;
+        assert_eq!((3_333, 6_667), config.fee_split(10_000));
+        config.fee_bps = 10_000;
+        assert_eq!((u64::MAX, 0), config.fee_split(u64::MAX));
+
+        assert_eq!(Ok(()), config.validate());
+        config.fee_bps = 10_001;
+        assert_eq!(Err(ProgramError::InvalidArgument), config.validate());
+    }
+
+    #[test]
+    fn test_topic_options_capacity() {
+        let pk = Pubkey::new_unique();
+        let mut topic = Topic::new("test_topic", &pk);
+        assert_eq!(MAX_OPTIONS, topic.options_remaining_capacity());
+        assert!(!topic.is_full());
+
+        topic.add_option(&pk, "a").unwrap();
+        topic.add_option(&pk, "b").unwrap();
+        assert_eq!(MAX_OPTIONS - 2, topic.options_remaining_capacity());
+
+        topic.config.max_options = 3;
+        assert_eq!(1, topic.options_remaining_capacity());
+        topic.add_option(&pk, "c").unwrap();
+        assert_eq!(0, topic.options_remaining_capacity());
+        assert!(topic.is_full());
+        assert_eq!(
+            Err(TopicError::TopicFull.into()),
+            topic.add_option(&pk, "d")
+        );
+
+        topic.config.max_options = MAX_OPTIONS as u8 + 1;
+        assert_eq!(Err(ProgramError::InvalidArgument), topic.config.validate());
+    }
+
+    #[test]
+    fn test_topic_merge_tally() {
+        let pk = Pubkey::new_unique();
+        let mut shard_a = Topic::new("test_topic", &pk);
+        let mut shard_b = Topic::new("test_topic", &pk);
+        for shard in [&mut shard_a, &mut shard_b] {
+            shard.add_option(&pk, "yes").unwrap();
+            shard.add_option(&pk, "no").unwrap();
+        }
+        shard_a.vote(OptIdx(0), &Pubkey::new_unique(), 0).unwrap();
+        shard_a.vote(OptIdx(1), &Pubkey::new_unique(), 0).unwrap();
+        shard_b.vote(OptIdx(0), &Pubkey::new_unique(), 0).unwrap();
+        shard_b.anon_vote(OptIdx(0), &[1; 32]).unwrap();
+        assert_eq!(Ok(vec![(0, 3), (1, 1)]), shard_a.merge_tally(&shard_b));
+
+        shard_b.rename_option(OptIdx(1), "nope").unwrap();
+        assert_eq!(
+            Err(ProgramError::InvalidArgument),
+            shard_a.merge_tally(&shard_b)
+        );
+        shard_a.add_option(&pk, "maybe").unwrap();
+        assert_eq!(
+            Err(ProgramError::InvalidArgument),
+            shard_a.merge_tally(&shard_b)
+        );
+    }
+
+    #[test]
+    fn test_name_with_delimiter() {
+        let pk = Pubkey::new_unique();
+        let mut topic = Topic::new("test_topic", &pk);
+        assert_eq!(
+            Err(ProgramError::InvalidArgument),
+            topic.set_name("pizza|pasta")
+        );
+        assert_eq!(
+            Err(ProgramError::InvalidArgument),
+            Option::new(&pk, 0, "pizza|pasta")
+        );
+        assert_eq!(
+            Err(ProgramError::InvalidArgument),
+            topic.add_option(&pk, "pizza|pasta")
+        );
+        topic.add_option(&pk, "pizza").unwrap();
+        assert_eq!(
+            Err(ProgramError::InvalidArgument),
+            topic.rename_option(OptIdx(0), "pizza|pasta")
+        );
+        assert_eq!(Topic::new("test_topic", &pk).name, topic.name);
+    }
+
+    #[test]
+    fn test_topic_option_weight() {
+        let pk = Pubkey::new_unique();
+        let mut topic = Topic::new("test_topic", &pk);
+        topic.config.weight_by_lamports = true;
+        topic.add_option(&pk, "yes").unwrap();
+        topic.add_option(&pk, "no").unwrap();
+        for (opt_idx, weight) in [(0, 5), (0, 7), (1, 3)] {
+            topic
+                .vote(OptIdx(opt_idx), &Pubkey::new_unique(), 0)
+                .unwrap();
+            topic.options[opt_idx as usize].add_weight(weight).unwrap();
+        }
+        assert_eq!(Ok(12), topic.option_weight(0));
+        assert_eq!(Ok(3), topic.option_weight(1));
+        assert_eq!(2, topic.options[0].vote_count());
+        assert_eq!(Err(ProgramError::InvalidArgument), topic.option_weight(2));
+    }
+
+    #[test]
+    fn test_topic_recompute_cache() {
+        let pk = Pubkey::new_unique();
+        let mut topic = Topic::new("test_topic", &pk);
+        topic.add_option(&pk, "yes").unwrap();
+        topic.vote(OptIdx(0), &Pubkey::new_unique(), 0).unwrap();
+        topic.anon_vote(OptIdx(0), &[1; 32]).unwrap();
+        assert_eq!(2, topic.cached_total_votes);
+
+        topic.cached_total_votes = 40;
+        topic.recompute_cache();
+        assert_eq!(2, topic.cached_total_votes);
+
+        topic.reset();
+        assert_eq!(0, topic.cached_total_votes);
+    }
+
+    #[test]
+    fn test_topic_state() {
+        let pk = Pubkey::new_unique();
+        assert_eq!(TopicState::Uninitialized, Topic::default().state());
+
+        let mut topic = Topic::new("test_topic", &pk);
+        assert_eq!(TopicState::Draft, topic.state());
+        topic.add_option(&pk, "option_name").unwrap();
+        assert_eq!(TopicState::Open, topic.state());
+
+        topic.is_paused = true;
+        assert_eq!(TopicState::Paused, topic.state());
+        topic.is_finished = true;
+        assert_eq!(TopicState::Finished, topic.state());
+        topic.is_paused = false;
+        assert_eq!(TopicState::Finished, topic.state());
+
+        topic.is_finished = false;
+        topic.options[0] = Option::default();
+        assert_eq!(TopicState::Draft, topic.state());
+    }
+
+    #[test]
+    fn test_topic_vote_with_token_account() {
+        let pk = Pubkey::new_unique();
+        let token_account = Pubkey::new_unique();
+        let (wallet_a, wallet_b) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let mut topic = Topic::new("test_topic", &pk);
+        topic.add_option(&pk, "yes").unwrap();
+        topic.add_option(&pk, "no").unwrap();
+
+        topic
+            .vote_with_token_account(OptIdx(0), &wallet_a, &token_account, 0)
+            .unwrap();
+        assert_eq!(&[token_account], topic.options[0].active_token_accounts());
+        assert_eq!(
+            Err(TopicError::TokenAccountUsed.into()),
+            topic.vote_with_token_account(OptIdx(1), &wallet_b, &token_account, 0)
+        );
+        assert_eq!(1, topic.total_votes());
+
+        topic
+            .vote_with_token_account(OptIdx(1), &wallet_b, &Pubkey::new_unique(), 0)
+            .unwrap();
+        let mut data = Topic::empty_bytes();
+        topic.pack_into_slice(&mut data).unwrap();
+        assert_eq!(Ok(topic), Topic::unpack_from_slice(&data));
+    }
+
+    #[test]
+    fn test_topic_eligible_voter_count() {
+        let pk = Pubkey::new_unique();
+        let mut topic = Topic::new("test_topic", &pk);
+        topic.add_option(&pk, "yes").unwrap();
+        topic.add_option(&pk, "no").unwrap();
+        assert_eq!(OPEN_VOTING, topic.eligible_voter_count());
+        assert!(topic.is_eligible(&pk));
+
+        let voters = [
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+        ];
+        for voter in &voters {
+            topic.add_whitelisted(voter, 1).unwrap();
+        }
+        topic.add_whitelisted(&voters[0], 1).unwrap();
+        assert_eq!(3, topic.eligible_voter_count());
+        assert!(topic.is_eligible(&voters[2]));
+        assert!(!topic.is_eligible(&pk));
+
+        topic.vote(OptIdx(0), &voters[0], 0).unwrap();
+        topic.vote(OptIdx(1), &voters[0], 0).unwrap();
+        topic.vote(OptIdx(1), &voters[1], 0).unwrap();
+        topic.anon_vote(OptIdx(0), &[1; 32]).unwrap();
+        assert_eq!(2, topic.unique_voter_count());
+
+        for _ in 3..MAX_WHITELIST {
+            topic.add_whitelisted(&Pubkey::new_unique(), 1).unwrap();
+        }
+        assert_eq!(
+            Err(ProgramError::InvalidArgument),
+            topic.add_whitelisted(&Pubkey::new_unique(), 1)
+        );
+        let mut data = Topic::empty_bytes();
+        topic.pack_into_slice(&mut data).unwrap();
+        assert_eq!(Ok(topic), Topic::unpack_from_slice(&data));
+    }
+
+    #[cfg(feature = "test-utils")]
+    #[test]
+    fn test_option_test_utils_getters() {
+        let pk = Pubkey::new_unique();
+        let voter = Pubkey::new_unique();
+        let token_account = Pubkey::new_unique();
+        let mut topic = Topic::new("test_topic", &pk);
+        topic.add_option(&pk, "yes").unwrap();
+        topic
+            .vote_with_token_account(OptIdx(0), &voter, &token_account, 42)
+            .unwrap();
+        topic.anon_vote(OptIdx(0), &[1; 32]).unwrap();
+        topic.options[0].add_weight(7).unwrap();
+
+        let opt = &topic.options[0];
+        assert_eq!(&pk, opt.belongs_to());
+        assert_eq!(0, opt.belongs_idx());
+        assert_eq!(Ok("yes"), crate::util::str_unpack(opt.name()));
+        assert_eq!(voter, opt.voters()[0]);
+        assert_eq!(1, opt.current_voter_index());
+        assert_eq!(1, opt.anon_votes());
+        assert_eq!(42, opt.vote_times()[0]);
+        assert_eq!(7, opt.weight());
+        assert_eq!(token_account, opt.token_accounts()[0]);
+    }
+
+    #[test]
+    fn test_snapshot_hash() {
+        let pk = Pubkey::new_unique();
+        let mut topic = Topic::new("test_topic", &pk);
+        topic.add_option(&pk, "yes").unwrap();
+        let before = topic.snapshot_hash();
+
+        let mut buf = Topic::empty_bytes();
+        topic.pack_into_slice(&mut buf).unwrap();
+        let repacked = Topic::unpack_from_slice(&buf).unwrap();
+        assert_eq!(before, repacked.snapshot_hash());
+
+        topic.vote(OptIdx(0), &Pubkey::new_unique(), 0).unwrap();
+        assert_ne!(before, topic.snapshot_hash());
+    }
+
+    #[test]
+    fn test_topic_winner_voters() {
+        let pk = Pubkey::new_unique();
+        let mut topic = Topic::new("test_topic", &pk);
+        topic.add_option(&pk, "yes").unwrap();
+        topic.add_option(&pk, "no").unwrap();
+        let (alice, bob, carol) = (
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+        );
+        topic.vote(OptIdx(1), &alice, 0).unwrap();
+        topic.vote(OptIdx(0), &bob, 0).unwrap();
+        topic.vote(OptIdx(1), &carol, 0).unwrap();
+        topic.anon_vote(OptIdx(1), &[1; 32]).unwrap();
+        assert_eq!(Err(ProgramError::InvalidAccountData), topic.winner_voters());
+
+        topic.finalize().unwrap();
+        assert_eq!(Ok(vec![alice, carol]), topic.winner_voters());
+    }
+
+    #[test]
+    fn test_topic_use_nonce() {
+        let pk = Pubkey::new_unique();
+        let voter = Pubkey::new_unique();
+        let mut topic = Topic::new("test_topic", &pk);
+        topic.use_nonce(&voter, 7).unwrap();
+        assert_eq!(
+            Err(TopicError::NonceReused.into()),
+            topic.use_nonce(&voter, 7)
+        );
+        topic.use_nonce(&voter, 8).unwrap();
+        topic.use_nonce(&Pubkey::new_unique(), 7).unwrap();
+
+        let mut data = Topic::empty_bytes();
+        topic.pack_into_slice(&mut data).unwrap();
+        let mut topic = Topic::unpack_from_slice(&data).unwrap();
+        assert_eq!(
+            Err(TopicError::NonceReused.into()),
+            topic.use_nonce(&voter, 8)
+        );
+
+        for nonce in 100..100 + MAX_SEEN_NONCES as u64 - 3 {
+            topic.use_nonce(&voter, nonce).unwrap();
+        }
+        assert_eq!(
+            Err(ProgramError::InvalidArgument),
+            topic.use_nonce(&voter, 1)
+        );
+        // a full set still recognises replays rather than evicting them
+        assert_eq!(
+            Err(TopicError::NonceReused.into()),
+            topic.use_nonce(&voter, 7)
+        );
+    }
+
+    #[test]
+    fn test_topic_validate_option_indices() {
+        let pk = Pubkey::new_unique();
+        let mut topic = Topic::new("test_topic", &pk);
+        topic.add_option(&pk, "yes").unwrap();
+        topic.add_option(&pk, "no").unwrap();
+        topic.validate_option_indices().unwrap();
+
+        for belongs_idx in [0, 2] {
+            topic.options[1].belongs_idx = belongs_idx;
+            let mut data = Topic::empty_bytes();
+            topic.pack_into_slice(&mut data).unwrap();
+            assert_eq!(
+                Err(ProgramError::InvalidAccountData),
+                Topic::unpack_from_slice(&data)
+            );
+        }
+
+        // a reorder leaves the indices permuted, which is valid
+        topic.options[0].belongs_idx = 1;
+        topic.options[1].belongs_idx = 0;
+        topic.validate_option_indices().unwrap();
+
+        topic.options[1].belongs_idx = 1;
+        topic.opt_current_idx = MAX_OPTIONS as u8 + 1;
+        assert_eq!(
+            Err(ProgramError::InvalidAccountData),
+            topic.validate_option_indices()
+        );
+    }
+
+    #[test]
+    fn test_topic_validate_counts() {
+        let pk = Pubkey::new_unique();
+        let mut topic = Topic::new("test_topic", &pk);
+        topic.add_option(&pk, "yes").unwrap();
+        topic.validate_counts().unwrap();
+
+        let corruptions: [fn(&mut Topic); 5] = [
+            |t| t.nullifier_count = MAX_NULLIFIERS as u8 + 1,
+            |t| t.banned_count = MAX_BANNED as u8 + 1,
+            |t| t.whitelist_count = MAX_WHITELIST as u8 + 1,
+            |t| t.seen_nonce_count = MAX_SEEN_NONCES as u8 + 1,
+            |t| t.options[0].current_voter_index = MAX_VOTERS as u8 + 1,
+        ];
+        for corrupt in corruptions {
+            let mut corrupted = topic.clone();
+            corrupt(&mut corrupted);
+            let mut data = Topic::empty_bytes();
+            corrupted.pack_into_slice(&mut data).unwrap();
+            assert_eq!(
+                Err(ProgramError::InvalidAccountData),
+                Topic::unpack_from_slice(&data)
+            );
+        }
+    }
+
+    #[test]
+    fn test_topic_can_add_option() {
+        let pk = Pubkey::new_unique();
+        let mut topic = Topic::new("test_topic", &pk);
+        topic.can_add_option().unwrap();
+        topic.add_option(&pk, "yes").unwrap();
+        topic.can_add_option().unwrap();
+
+        topic.is_paused = true;
+        assert_eq!(
+            Err(ProgramError::InvalidAccountData),
+            topic.can_add_option()
+        );
+        topic.is_paused = false;
+
+        topic.config.lock_options_on_first_vote = true;
+        topic.vote(OptIdx(0), &Pubkey::new_unique(), 0).unwrap();
+        assert_eq!(
+            Err(TopicError::OptionsLocked.into()),
+            topic.can_add_option()
+        );
+        topic.config.lock_options_on_first_vote = false;
+
+        topic.config.max_options = 1;
+        assert_eq!(Err(TopicError::TopicFull.into()), topic.can_add_option());
+        topic.config.max_options = 0;
+
+        topic.finalize().unwrap();
+        assert_eq!(
+            Err(ProgramError::InvalidAccountData),
+            topic.can_add_option()
+        );
+        assert_eq!(
+            Err(ProgramError::InvalidAccountData),
+            Topic::default().can_add_option()
+        );
+    }
+
+    #[test]
+    fn test_topic_can_vote() {
+        let pk = Pubkey::new_unique();
+        let voter = Pubkey::new_unique();
+        let mut topic = Topic::new("test_topic", &pk);
+        assert_eq!(
+            Err(ProgramError::InvalidAccountData),
+            topic.can_vote(&voter, 0)
+        );
+        topic.add_option(&pk, "yes").unwrap();
+        topic.can_vote(&voter, 0).unwrap();
+
+        topic.is_paused = true;
+        assert_eq!(
+            Err(TopicError::TopicPaused.into()),
+            topic.can_vote(&voter, 0)
+        );
+        topic.is_paused = false;
+
+        topic.config.deadline = 100;
+        topic.can_vote(&voter, 100).unwrap();
+        assert_eq!(
+            Err(TopicError::DeadlinePassed.into()),
+            topic.can_vote(&voter, 101)
+        );
+        topic.config.deadline = 0;
+
+        topic.ban_voter(&voter).unwrap();
+        assert_eq!(
+            Err(TopicError::VoterBanned.into()),
+            topic.can_vote(&voter, 0)
+        );
+        topic.banned_count = 0;
+
+        topic.add_whitelisted(&pk, 1).unwrap();
+        assert_eq!(
+            Err(TopicError::VoterNotWhitelisted.into()),
+            topic.can_vote(&voter, 0)
+        );
+        topic.add_whitelisted(&voter, 1).unwrap();
+        topic.can_vote(&voter, 0).unwrap();
+
+        topic.vote(OptIdx(0), &voter, 0).unwrap();
+        assert_eq!(
+            Err(TopicError::AlreadyVoted.into()),
+            topic.can_vote(&voter, 0)
+        );
+
+        topic.finalize().unwrap();
+        assert_eq!(
+            Err(TopicError::AlreadyFinished.into()),
+            topic.can_vote(&pk, 0)
+        );
+    }
+
+    #[test]
+    fn test_topic_quadratic_tally() {
+        let pk = Pubkey::new_unique();
+        let mut topic = Topic::new("test_topic", &pk);
+        topic.config.tally_mode = TallyMode::Quadratic;
+        topic.add_option(&pk, "yes").unwrap();
+        topic.add_option(&pk, "no").unwrap();
+
+        topic.vote(OptIdx(0), &Pubkey::new_unique(), 0).unwrap();
+        topic.options[0]
+            .add_weight(TallyMode::quadratic_weight(99))
+            .unwrap();
+        for _ in 0..2 {
+            topic.vote(OptIdx(1), &Pubkey::new_unique(), 0).unwrap();
+            topic.options[1]
+                .add_weight(TallyMode::quadratic_weight(25))
+                .unwrap();
+        }
+        assert_eq!(9, topic.option_score(&topic.options[0]));
+        assert_eq!(10, topic.option_score(&topic.options[1]));
+
+        topic.finalize().unwrap();
+        assert_eq!(1, topic.result_idx);
+
+        let mut data = Topic::empty_bytes();
+        topic.pack_into_slice(&mut data).unwrap();
+        assert_eq!(Ok(topic), Topic::unpack_from_slice(&data));
+    }
+
+    #[test]
+    fn test_config_tally_mode() {
+        let mut config = TopicConfig {
+            tally_mode: TallyMode::Quadratic,
+            ..TopicConfig::default()
+        };
+        config.validate().unwrap();
+        config.weight_by_lamports = true;
+        assert_eq!(Err(ProgramError::InvalidArgument), config.validate());
+
+        let mut data = [0; CONFIG_LEN];
+        data[CONFIG_LEN - 93] = 4;
+        assert_eq!(
+            Err(ProgramError::InvalidAccountData),
+            TopicConfig::unpack_from_slice(&data)
+        );
+    }
+
+    #[test]
+    fn test_topic_reset_result() {
+        let pk = Pubkey::new_unique();
+        let mut topic = Topic::new("test_topic", &pk);
+        topic.add_option(&pk, "yes").unwrap();
+        topic.add_option(&pk, "no").unwrap();
+        topic.vote(OptIdx(1), &Pubkey::new_unique(), 0).unwrap();
+        let open = topic.clone();
+
+        topic.finalize().unwrap();
+        topic.note[..9].copy_from_slice(b"certified");
+        assert_eq!(1, topic.result_idx);
+        topic.reset_result();
+        assert_eq!(open, topic);
+    }
+
+    #[test]
+    fn test_topic_whitelist_weight() {
+        let pk = Pubkey::new_unique();
+        let (alice, bob) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let mut topic = Topic::new("test_topic", &pk);
+        topic.add_whitelisted(&alice, 3).unwrap();
+        topic.add_whitelisted(&bob, 1).unwrap();
+        assert_eq!(3, topic.whitelist_weight(&alice));
+        assert_eq!(0, topic.whitelist_weight(&pk));
+
+        topic.add_whitelisted(&alice, 5).unwrap();
+        assert_eq!(5, topic.whitelist_weight(&alice));
+        assert_eq!(2, topic.eligible_voter_count());
+
+        let mut data = Topic::empty_bytes();
+        topic.pack_into_slice(&mut data).unwrap();
+        assert_eq!(Ok(topic), Topic::unpack_from_slice(&data));
+    }
+
+    #[test]
+    fn test_topic_option_voters() {
+        let pk = Pubkey::new_unique();
+        let mut topic = Topic::new("test_topic", &pk);
+        topic.add_option(&pk, "yes").unwrap();
+        topic.add_option(&pk, "no").unwrap();
+        let (alice, bob) = (Pubkey::new_unique(), Pubkey::new_unique());
+        topic.vote(OptIdx(1), &alice, 0).unwrap();
+        topic.vote(OptIdx(1), &bob, 0).unwrap();
+        topic.anon_vote(OptIdx(0), &[1; 32]).unwrap();
+
+        assert_eq!(Ok(&[][..]), topic.option_voters(0));
+        assert_eq!(Ok(&[alice, bob][..]), topic.option_voters(1));
+        assert_eq!(Err(ProgramError::InvalidArgument), topic.option_voters(2));
+        assert_eq!(
+            Err(ProgramError::InvalidArgument),
+            topic.option_voters(MAX_OPTIONS as u8)
+        );
+    }
+
+    #[test]
+    fn test_topic_options_with_votes() {
+        let pk = Pubkey::new_unique();
+        let mut topic = Topic::new("test_topic", &pk);
+        assert!(topic.options_with_votes().is_empty());
+        topic.add_option(&pk, "yes").unwrap();
+        topic.add_option(&pk, "no").unwrap();
+        topic.add_option(&pk, "maybe").unwrap();
+        let (alice, bob, carol) = (
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+        );
+        topic.vote(OptIdx(2), &alice, 0).unwrap();
+        topic.vote(OptIdx(0), &bob, 0).unwrap();
+        topic.vote(OptIdx(2), &carol, 0).unwrap();
+        topic.anon_vote(OptIdx(1), &[1; 32]).unwrap();
+
+        assert_eq!(
+            vec![
+                ("yes".to_string(), vec![bob]),
+                ("no".to_string(), vec![]),
+                ("maybe".to_string(), vec![alice, carol]),
+            ],
+            topic.options_with_votes()
+        );
+    }
+
+    #[test]
+    fn test_topic_new_with_options() {
+        let (pk, topic_key) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let topic = Topic::new_with_options("test_topic", &pk, &topic_key, &["yes", "no", "maybe"])
+            .unwrap();
+        assert_eq!(3, topic.opt_current_idx);
+        assert_eq!(TopicState::Open, topic.state());
+        for (i, (idx, opt)) in topic.options_iter().enumerate() {
+            assert_eq!(i as u8, idx);
+            assert_eq!(topic_key, opt.belongs_to);
+            assert_eq!(i as u8, opt.belongs_idx);
+        }
+        let names: Vec<_> = topic
+            .options_iter()
+            .map(|(_, opt)| crate::util::str_unpack(&opt.name).unwrap())
+            .collect();
+        assert_eq!(vec!["yes", "no", "maybe"], names);
+
+        assert_eq!(
+            Ok(Topic::new("pizza", &pk)),
+            Topic::new_with_options("pizza", &pk, &topic_key, &[])
+        );
+        assert_eq!(
+            Err(ProgramError::InvalidArgument),
+            Topic::new_with_options("topic", &pk, &topic_key, &["a|b"])
+        );
+        let too_many = ["o"; MAX_OPTIONS + 1];
+        assert_eq!(
+            Err(TopicError::TopicFull.into()),
+            Topic::new_with_options("topic", &pk, &topic_key, &too_many)
+        );
+    }
+
+    #[test]
+    fn test_topic_voted_option() {
+        let pk = Pubkey::new_unique();
+        let voter = Pubkey::new_unique();
+        let mut topic = Topic::new_with_options("test_topic", &pk, &pk, &["yes", "no"]).unwrap();
+        assert_eq!(None, topic.voted_option(&voter));
+        topic.vote(OptIdx(1), &voter, 0).unwrap();
+        assert_eq!(Some(1), topic.voted_option(&voter));
+        assert_eq!(None, topic.voted_option(&pk));
+    }
+
+    #[test]
+    fn test_topic_weight_overflow() {
+        let pk = Pubkey::new_unique();
+        let mut topic = Topic::new_with_options("test_topic", &pk, &pk, &["yes", "no"]).unwrap();
+        topic.config.weight_by_lamports = true;
+        topic.vote(OptIdx(0), &Pubkey::new_unique(), 0).unwrap();
+        topic.vote(OptIdx(1), &Pubkey::new_unique(), 0).unwrap();
+
+        topic.options[0].add_weight(u64::MAX - 1).unwrap();
+        topic.options[0].add_weight(1).unwrap();
+        assert_eq!(
+            Err(ProgramError::ArithmeticOverflow),
+            topic.options[0].add_weight(1)
+        );
+        assert_eq!(Ok(u64::MAX), topic.total_score());
+
+        topic.options[1].add_weight(1).unwrap();
+        assert_eq!(Err(ProgramError::ArithmeticOverflow), topic.total_score());
+        let margin = topic.winning_margin_percent();
+        assert!(margin > 99.9 && margin <= 100.0);
+        topic.finalize().unwrap();
+        assert_eq!(0, topic.result_idx);
+        assert_eq!(2, topic.total_votes());
+    }
+
+    #[test]
+    fn test_topic_first_option() {
+        let pk = Pubkey::new_unique();
+        assert_eq!(None, Topic::new("test_topic", &pk).first_option());
+
+        let topic = Topic::new_with_options("test_topic", &pk, &pk, &["yes", "no"]).unwrap();
+        assert_eq!(Some(&topic.options[0]), topic.first_option());
+    }
+
+    #[test]
+    fn test_topic_set_quorum() {
+        let pk = Pubkey::new_unique();
+        let mut topic = Topic::new_with_options("test_topic", &pk, &pk, &["yes"]).unwrap();
+        topic.set_quorum(5).unwrap();
+        topic.set_quorum(10).unwrap();
+        assert_eq!(Err(ProgramError::InvalidArgument), topic.set_quorum(0));
+
+        for _ in 0..3 {
+            topic.vote(OptIdx(0), &Pubkey::new_unique(), 0).unwrap();
+        }
+        assert_eq!(Err(ProgramError::InvalidArgument), topic.set_quorum(3));
+        topic.set_quorum(2).unwrap();
+        assert_eq!(2, topic.config.min_quorum);
+
+        topic.config.auto_finish_on_quorum = true;
+        assert_eq!(Err(ProgramError::InvalidArgument), topic.set_quorum(0));
+    }
+
+    #[test]
+    fn test_topic_vote_with_receipt() {
+        let pk = Pubkey::new_unique();
+        let mut topic = Topic::new_with_options("test_topic", &pk, &pk, &["yes", "no"]).unwrap();
+        for expected in 1..=3 {
+            assert_eq!(
+                Ok(expected),
+                topic.vote_with_receipt(OptIdx(0), &Pubkey::new_unique(), 0)
+            );
+        }
+        assert_eq!(
+            Ok(1),
+            topic.vote_with_receipt(OptIdx(1), &Pubkey::new_unique(), 0)
+        );
+        assert_eq!(
+            Err(ProgramError::InvalidArgument),
+            topic.vote_with_receipt(OptIdx(2), &Pubkey::new_unique(), 0)
+        );
+    }
+
+    #[test]
+    fn test_topic_record_action() {
+        let pk = Pubkey::new_unique();
+        let mut topic = Topic::new("test_topic", &pk);
+        topic.record_action(100).unwrap();
+        topic.record_action(100).unwrap();
+
+        topic.config.min_action_interval = 10;
+        assert_eq!(Err(TopicError::TooSoon.into()), topic.record_action(109));
+        assert_eq!(100, topic.last_action_at);
+        topic.record_action(110).unwrap();
+        assert_eq!(110, topic.last_action_at);
+
+        topic.config.min_action_interval = -1;
+        assert_eq!(Err(ProgramError::InvalidArgument), topic.config.validate());
+    }
+
+    #[test]
+    fn test_topic_counts_for() {
+        let pk = Pubkey::new_unique();
+        let mut topic =
+            Topic::new_with_options("test_topic", &pk, &pk, &["yes", "no", "maybe"]).unwrap();
+        topic.vote(OptIdx(0), &Pubkey::new_unique(), 0).unwrap();
+        topic.vote(OptIdx(2), &Pubkey::new_unique(), 0).unwrap();
+        topic.vote(OptIdx(2), &Pubkey::new_unique(), 0).unwrap();
+        topic.anon_vote(OptIdx(2), &[1; 32]).unwrap();
+
+        assert_eq!(Ok(vec![3, 0, 1]), topic.counts_for(&[2, 1, 0]));
+        assert_eq!(Ok(vec![]), topic.counts_for(&[]));
+        assert_eq!(
+            Err(ProgramError::InvalidArgument),
+            topic.counts_for(&[0, 3])
+        );
+        assert_eq!(
+            Err(ProgramError::InvalidArgument),
+            topic.counts_for(&[MAX_OPTIONS as u8])
+        );
+    }
+
+    #[test]
+    fn test_topic_options_ever_added() {
+        let pk = Pubkey::new_unique();
+        let mut topic = Topic::new("test_topic", &pk);
+        assert_eq!(0, topic.options_ever_added);
+        topic.add_option(&pk, "yes").unwrap();
+        topic.add_option(&pk, "no").unwrap();
+        assert_eq!(2, topic.options_ever_added);
+
+        topic.reset();
+        assert_eq!(0, topic.opt_current_idx);
+        assert_eq!(2, topic.options_ever_added);
+        topic.add_option(&pk, "maybe").unwrap();
+        assert_eq!(3, topic.options_ever_added);
+
+        let mut data = vec![0; Topic::get_packed_len()];
+        topic.pack_into_slice(&mut data).unwrap();
+        assert_eq!(
+            Ok(3),
+            Topic::unpack_from_slice(&data).map(|t| t.options_ever_added)
+        );
+    }
+
+    #[test]
+    fn test_topic_vote_with_token_balances() {
+        let pk = Pubkey::new_unique();
+        let mut topic = Topic::new_with_options("test_topic", &pk, &pk, &["yes", "no"]).unwrap();
+        topic.config.tally_mode = TallyMode::TokenWeight;
+        let (voter, mint) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let source = |amount| {
+            let data = TokenAccountData {
+                mint,
+                owner: voter,
+                amount,
+            };
+            (Pubkey::new_unique(), data)
+        };
+        let (a, b) = (source(30), source(12));
+
+        let mut mixed = source(5);
+        mixed.1.mint = Pubkey::new_unique();
+        assert_eq!(
+            Err(TopicError::TokenMintMismatch.into()),
+            topic.vote_with_token_balances(OptIdx(0), &voter, &[a, mixed], 0)
+        );
+        assert_eq!(
+            Err(TopicError::TokenAccountUsed.into()),
+            topic.vote_with_token_balances(OptIdx(0), &voter, &[a, a], 0)
+        );
+        assert_eq!(
+            Err(ProgramError::IllegalOwner),
+            topic.vote_with_token_balances(OptIdx(0), &pk, &[a], 0)
+        );
+        assert_eq!(0, topic.total_votes());
+
+        assert_eq!(
+            Ok(1),
+            topic.vote_with_token_balances(OptIdx(0), &voter, &[a, b], 0)
+        );
+        assert_eq!(42, topic.option_score(&topic.options[0]));
+        assert_eq!(&[a.0], topic.options[0].active_token_accounts());
+
+        let mut data = [0; 165];
+        data[..32].copy_from_slice(mint.as_ref());
+        data[32..64].copy_from_slice(voter.as_ref());
+        data[64..72].copy_from_slice(&30u64.to_le_bytes());
+        assert_eq!(Ok(a.1), TokenAccountData::unpack_from_slice(&data));
+        assert_eq!(
+            Err(ProgramError::InvalidAccountData),
+            TokenAccountData::unpack_from_slice(&data[..71])
+        );
+    }
+
+    #[test]
+    fn test_topic_retract_and_change_vote() {
+        let pk = Pubkey::new_unique();
+        let mut topic = Topic::new_with_options("test_topic", &pk, &pk, &["yes", "no"]).unwrap();
+        let (alice, bob, carol) = (
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+        );
+        topic.vote(OptIdx(0), &alice, 1).unwrap();
+        topic.vote(OptIdx(0), &bob, 2).unwrap();
+        topic.vote(OptIdx(0), &carol, 3).unwrap();
+
+        assert_eq!(Ok(OptIdx(0)), topic.retract_vote(&bob, 4));
+        assert_eq!(&[alice, carol], topic.options[0].active_voters());
+        assert_eq!(&[1, 3], topic.options[0].active_vote_times());
+        assert_eq!(2, topic.total_votes());
+        assert_eq!(
+            Err(ProgramError::InvalidArgument),
+            topic.retract_vote(&bob, 4)
+        );
+
+        assert_eq!(Ok(1), topic.change_vote(OptIdx(1), &alice, 5));
+        assert_eq!(&[carol], topic.options[0].active_voters());
+        assert_eq!(&[alice], topic.options[1].active_voters());
+        assert_eq!(
+            Err(ProgramError::InvalidArgument),
+            topic.change_vote(OptIdx(2), &carol, 5)
+        );
+        assert_eq!(2, topic.cached_total_votes);
+
+        topic.config.allow_vote_changes = false;
+        assert_eq!(
+            Err(TopicError::VoteChangesDisabled.into()),
+            topic.retract_vote(&carol, 6)
+        );
+        assert_eq!(
+            Err(TopicError::VoteChangesDisabled.into()),
+            topic.change_vote(OptIdx(1), &carol, 6)
+        );
+
+        topic.config.allow_vote_changes = true;
+        topic.config.tally_mode = TallyMode::WhitelistWeight;
+        assert_eq!(
+            Err(ProgramError::InvalidAccountData),
+            topic.retract_vote(&carol, 6)
+        );
+    }
+
+    #[test]
+    fn test_config_oracle_weighted() {
+        let mut config = TopicConfig {
+            oracle_weighted: true,
+            ..TopicConfig::default()
+        };
+        assert_eq!(Err(ProgramError::InvalidArgument), config.validate());
+        config.oracle_authority = Pubkey::new_unique();
+        config.validate().unwrap();
+        assert!(config.is_weighted());
+        config.tally_mode = TallyMode::Quadratic;
+        assert_eq!(Err(ProgramError::InvalidArgument), config.validate());
+
+        let voter = Pubkey::new_unique();
+        let mut data = [0; 40];
+        data[..32].copy_from_slice(voter.as_ref());
+        data[32..].copy_from_slice(&7u64.to_le_bytes());
+        assert_eq!(
+            Ok(OracleWeight { voter, weight: 7 }),
+            OracleWeight::unpack_from_slice(&data)
+        );
+        assert_eq!(
+            Err(ProgramError::InvalidAccountData),
+            OracleWeight::unpack_from_slice(&data[..39])
+        );
+    }
+
+    #[test]
+    fn test_topic_is_draft() {
+        let pk = Pubkey::new_unique();
+        assert!(!Topic::default().is_draft());
+
+        let mut topic = Topic::new("test_topic", &pk);
+        topic.config.min_options = 3;
+        assert!(topic.is_draft());
+        topic.add_option(&pk, "yes").unwrap();
+        topic.add_option(&pk, "no").unwrap();
+        assert!(topic.is_draft());
+        assert_eq!(
+            Err(ProgramError::InvalidAccountData),
+            topic.can_vote(&pk, 0)
+        );
+
+        topic.add_option(&pk, "maybe").unwrap();
+        assert!(!topic.is_draft());
+        assert_eq!(TopicState::Open, topic.state());
+        topic.vote(OptIdx(0), &pk, 0).unwrap();
+
+        topic.options[2] = Option::default();
+        assert!(!topic.is_draft());
+        topic.is_finished = true;
+        assert!(!topic.is_draft());
+
+        topic.config.min_options = MAX_OPTIONS as u8 + 1;
+        assert_eq!(Err(ProgramError::InvalidArgument), topic.config.validate());
+        topic.config.min_options = 3;
+        topic.config.max_options = 2;
+        assert_eq!(Err(ProgramError::InvalidArgument), topic.config.validate());
+    }
+
+    #[test]
+    fn test_topic_turnout() {
+        let pk = Pubkey::new_unique();
+        let mut topic = Topic::new_with_options("test_topic", &pk, &pk, &["yes", "no"]).unwrap();
+        assert_eq!(0.0, topic.turnout());
+        topic.vote(OptIdx(0), &Pubkey::new_unique(), 0).unwrap();
+        topic.vote(OptIdx(1), &Pubkey::new_unique(), 0).unwrap();
+        topic.anon_vote(OptIdx(1), &[1; 32]).unwrap();
+        assert_eq!(2.0, topic.turnout());
+
+        let mut topic = Topic::new_with_options("test_topic", &pk, &pk, &["yes", "no"]).unwrap();
+        let voters = [
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+        ];
+        for voter in &voters {
+            topic.add_whitelisted(voter, 1).unwrap();
+        }
+        assert_eq!(0.0, topic.turnout());
+        topic.vote(OptIdx(0), &voters[0], 0).unwrap();
+        assert_eq!(0.25, topic.turnout());
+        topic.vote(OptIdx(1), &voters[3], 0).unwrap();
+        assert_eq!(0.5, topic.turnout());
+    }
+
+    #[test]
+    fn test_topic_pack_skips_unused_options() {
+        let pk = Pubkey::new_unique();
+        for count in 0..=MAX_OPTIONS {
+            let mut topic = Topic::new("test_topic", &pk);
+            for i in 0..count {
+                topic.add_option(&pk, &format!("option{}", i)).unwrap();
+                topic
+                    .vote(OptIdx(i as u8), &Pubkey::new_unique(), 0)
+                    .unwrap();
+            }
+
+            let mut data = vec![0xff; Topic::get_packed_len()];
+            topic.pack_into_slice(&mut data).unwrap();
+            let options_start = MAX_TOPIC_NAME;
+            for (i, option) in topic.options.iter().enumerate() {
+                let mut full = Option::empty_bytes();
+                option.pack_into_slice(&mut full);
+                let start = options_start + i * OPTION_LEN;
+                assert_eq!(&full[..], &data[start..start + OPTION_LEN]);
+            }
+            assert_eq!(Ok(&topic), Topic::unpack_from_slice(&data).as_ref());
+        }
+    }
+
+    #[test]
+    fn test_topic_verify_owner_signer() {
+        let (owner, stranger) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let topic = Topic::new("test_topic", &owner);
+        let mut lamports = 0;
+        let mut data = [];
+        let mut acc = AccountInfo::new(
+            &owner,
+            true,
+            false,
+            &mut lamports,
+            &mut data,
+            &owner,
+            false,
+            0,
+        );
+        topic.verify_owner_signer(&acc).unwrap();
+
+        acc.is_signer = false;
+        assert_eq!(
+            Err(ProgramError::MissingRequiredSignature),
+            topic.verify_owner_signer(&acc)
+        );
+        acc.key = &stranger;
+        assert_eq!(
+            Err(ProgramError::MissingRequiredSignature),
+            topic.verify_owner_signer(&acc)
+        );
+        acc.is_signer = true;
+        assert_eq!(
+            Err(TopicError::NotTopicOwner.into()),
+            topic.verify_owner_signer(&acc)
+        );
+    }
+
+    #[test]
+    fn test_topic_salted_commitments() {
+        let pk = Pubkey::new_unique();
+        let mut topic_a = Topic::new_with_options("test_topic", &pk, &pk, &["yes", "no"]).unwrap();
+        let mut topic_b = topic_a.clone();
+        topic_a.config.salt = [1; 16];
+        topic_b.config.salt = [2; 16];
+        let secret = [7; 32];
+
+        assert_ne!(
+            topic_a.commitment(OptIdx(0), &secret),
+            topic_b.commitment(OptIdx(0), &secret)
+        );
+        assert_ne!(topic_a.nullifier(&secret), topic_b.nullifier(&secret));
+        assert_ne!(
+            topic_a.commitment(OptIdx(0), &secret),
+            topic_a.commitment(OptIdx(1), &secret)
+        );
+        assert_ne!(
+            topic_a.nullifier(&secret),
+            topic_a.commitment(OptIdx(0), &secret)
+        );
+
+        topic_b.config.salt = topic_a.config.salt;
+        assert_eq!(
+            topic_a.commitment(OptIdx(1), &secret),
+            topic_b.commitment(OptIdx(1), &secret)
+        );
+        assert_eq!(topic_a.nullifier(&secret), topic_b.nullifier(&secret));
+    }
+
+    #[test]
+    fn test_topic_result_is_final() {
+        let pk = Pubkey::new_unique();
+        let mut topic = Topic::new("test_topic", &pk);
+        topic.add_option(&pk, "option_1").unwrap();
+        assert!(!topic.result_is_final());
+        assert_eq!(Err(ProgramError::InvalidAccountData), topic.certify());
+
+        topic.finalize().unwrap();
+        assert!(topic.is_finished);
+        assert!(!topic.result_is_final());
+
+        topic.certify().unwrap();
+        assert!(topic.result_is_final());
+        let mut data = Topic::empty_bytes();
+        topic.pack_into_slice(&mut data).unwrap();
+        assert!(Topic::unpack_from_slice(&data).unwrap().result_is_final());
+
+        topic.reset_result();
+        assert!(!topic.certified);
+        assert!(!topic.result_is_final());
+    }
+
+    #[test]
+    fn test_topic_option_by_belongs_idx() {
+        let pk = Pubkey::new_unique();
+        let mut topic = Topic::new("test_topic", &pk);
+        assert_eq!(None, topic.option_by_belongs_idx(0));
+        topic.add_option(&pk, "a").unwrap();
+        topic.add_option(&pk, "b").unwrap();
+        topic.add_option(&pk, "c").unwrap();
+        topic
+            .reorder_options(&[2, 0, 1, 3, 4, 5, 6, 7, 8, 9])
+            .unwrap();
+        topic.add_option(&pk, "d").unwrap();
+        let mut data = Topic::empty_bytes();
+        topic.pack_into_slice(&mut data).unwrap();
+        let topic = Topic::unpack_from_slice(&data).unwrap();
+
+        // slots now hold c, a, b, d while each option keeps the index it was added with
+        for (idx, name, slot) in [(0, "a", 1), (1, "b", 2), (2, "c", 0), (3, "d", 3)] {
+            let opt = topic.option_by_belongs_idx(idx).unwrap();
+            assert_eq!(name.as_bytes(), &opt.name[..1]);
+            assert_eq!(&topic.options[slot], opt);
+        }
+        assert_eq!(None, topic.option_by_belongs_idx(4));
     }
 }
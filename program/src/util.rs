@@ -1,24 +1,34 @@
-use std::{convert::TryInto, str::from_utf8};
+use solana_program::program_error::ProgramError;
+use std::str::from_utf8;
 
-use arrayref::{array_mut_ref, array_ref, array_refs, mut_array_refs};
-use solana_program::{entrypoint::ProgramResult, program_error::ProgramError};
-
-pub fn str_unpack<'a>(src: &'a [u8; 100]) -> &'a str {
+fn str_body(src: &[u8; 100]) -> &[u8] {
     let mut split_index: usize = 0;
     for (i, char) in src.iter().enumerate() {
-        if *char == '|' as u8 {
+        if *char == b'|' {
             split_index = i;
             break;
         }
     }
-    if split_index == 0 {
-        return ""
-    }
-
     let (str_body, _) = src.split_at(split_index);
+    str_body
+}
 
-    let str = from_utf8(str_body).unwrap();
-    str
+pub fn str_unpack(src: &[u8; 100]) -> Result<&str, ProgramError> {
+    from_utf8(str_body(src)).map_err(|_| ProgramError::InvalidAccountData)
+}
+
+/// Display-only variant of `str_unpack` that replaces invalid UTF-8 instead of failing.
+pub fn str_unpack_lossy(src: &[u8; 100]) -> String {
+    String::from_utf8_lossy(str_body(src)).into_owned()
+}
+
+/// Rejects strings containing the `|` delimiter, which `str_unpack` would truncate at, and
+/// strings too long for `str_pack` to fit alongside the delimiter.
+pub fn str_check(str: &str) -> Result<(), ProgramError> {
+    if str.len() >= 100 || str.as_bytes().contains(&b'|') {
+        return Err(ProgramError::InvalidArgument);
+    }
+    Ok(())
 }
 
 pub fn str_pack(str: &str, dst: &mut [u8; 100]) {
@@ -26,7 +36,78 @@ pub fn str_pack(str: &str, dst: &mut [u8; 100]) {
     for (i, char) in str_bytes.iter().enumerate() {
         dst[i] = *char
     }
-    dst[str_bytes.len()] = '|' as u8;
+    dst[str_bytes.len()] = b'|';
+}
+
+/// Decodes a base58 pubkey. Meant for consts, where invalid input panics and so fails the build.
+pub const fn decode_pubkey(src: &str) -> [u8; 32] {
+    const ALPHABET: &[u8; 58] = b"123456789ABCDEFGHJKLMNPQRSTUVWXYZabcdefghijkmnopqrstuvwxyz";
+    let src = src.as_bytes();
+    if src.is_empty() || src.len() > 44 {
+        panic!("pubkey must be 1 to 44 base58 characters");
+    }
+    let mut out = [0; 32];
+    let mut i = 0;
+    while i < src.len() {
+        let mut digit = 0;
+        while digit < ALPHABET.len() && ALPHABET[digit] != src[i] {
+            digit += 1;
+        }
+        if digit == ALPHABET.len() {
+            panic!("invalid base58 character in pubkey");
+        }
+        let mut carry = digit as u32;
+        let mut j = out.len();
+        while j > 0 {
+            j -= 1;
+            carry += out[j] as u32 * 58;
+            out[j] = carry as u8;
+            carry >>= 8;
+        }
+        if carry != 0 {
+            panic!("pubkey does not fit 32 bytes");
+        }
+        i += 1;
+    }
+    out
+}
+
+pub fn pack_u32(value: u32, dst: &mut [u8; 4]) {
+    *dst = value.to_le_bytes();
+}
+
+pub fn unpack_u32(src: &[u8; 4]) -> u32 {
+    u32::from_le_bytes(*src)
+}
+
+pub fn pack_u64(value: u64, dst: &mut [u8; 8]) {
+    *dst = value.to_le_bytes();
+}
+
+pub fn unpack_u64(src: &[u8; 8]) -> u64 {
+    u64::from_le_bytes(*src)
+}
+
+pub fn pack_i64(value: i64, dst: &mut [u8; 8]) {
+    *dst = value.to_le_bytes();
+}
+
+pub fn unpack_i64(src: &[u8; 8]) -> i64 {
+    i64::from_le_bytes(*src)
+}
+
+/// Largest `r` with `r * r <= n`.
+pub fn isqrt(n: u64) -> u64 {
+    if n < 2 {
+        return n;
+    }
+    let mut x = n;
+    let mut y = n / 2;
+    while y < x {
+        x = y;
+        y = (x + n / x) / 2;
+    }
+    x
 }
 
 #[cfg(test)]
@@ -40,6 +121,102 @@ mod tests {
         str_pack(str_test, &mut str_bytes);
 
         let unpack_str = str_unpack(&str_bytes);
-        assert_eq!(str_test, unpack_str);
+        assert_eq!(Ok(str_test), unpack_str);
+    }
+
+    #[test]
+    fn test_str_unpack_invalid_utf8() {
+        let mut str_bytes: [u8; 100] = [0; 100];
+        str_bytes[..3].copy_from_slice(&[0xff, 0xfe, b'|']);
+        assert_eq!(
+            Err(ProgramError::InvalidAccountData),
+            str_unpack(&str_bytes)
+        );
+    }
+
+    #[test]
+    fn test_str_unpack_lossy() {
+        let mut str_bytes: [u8; 100] = [0; 100];
+        str_bytes[..5].copy_from_slice(&[b'a', 0xff, b'b', 0xfe, b'|']);
+        assert_eq!("a\u{fffd}b\u{fffd}", str_unpack_lossy(&str_bytes));
+
+        str_pack("tests", &mut str_bytes);
+        assert_eq!("tests", str_unpack_lossy(&str_bytes));
+    }
+
+    #[test]
+    fn test_str_check() {
+        assert_eq!(Ok(()), str_check("tests"));
+        assert_eq!(Err(ProgramError::InvalidArgument), str_check("te|sts"));
+        assert_eq!(Ok(()), str_check(&"a".repeat(99)));
+        assert_eq!(
+            Err(ProgramError::InvalidArgument),
+            str_check(&"a".repeat(100))
+        );
+    }
+
+    #[test]
+    fn test_decode_pubkey() {
+        use solana_program::pubkey::Pubkey;
+        use std::str::FromStr;
+
+        for key in [
+            Pubkey::default(),
+            Pubkey::new_from_array([0xff; 32]),
+            Pubkey::new_unique(),
+            Pubkey::from_str("Vote111111111111111111111111111111111111111").unwrap(),
+        ] {
+            assert_eq!(key.to_bytes(), decode_pubkey(&key.to_string()));
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "invalid base58 character")]
+    fn test_decode_pubkey_invalid() {
+        decode_pubkey("0OIl");
+    }
+
+    #[test]
+    fn test_pack_unpack_u32() {
+        for value in [0, 1, 0x1234_5678, u32::MAX] {
+            let mut bytes = [0; 4];
+            pack_u32(value, &mut bytes);
+            assert_eq!(value, unpack_u32(&bytes));
+        }
+    }
+
+    #[test]
+    fn test_pack_unpack_u64() {
+        for value in [0, 1, 0x1234_5678_9abc_def0, u64::MAX] {
+            let mut bytes = [0; 8];
+            pack_u64(value, &mut bytes);
+            assert_eq!(value, unpack_u64(&bytes));
+        }
+    }
+
+    #[test]
+    fn test_pack_unpack_i64() {
+        for value in [0, 1, -1, i64::MIN, i64::MAX] {
+            let mut bytes = [0; 8];
+            pack_i64(value, &mut bytes);
+            assert_eq!(value, unpack_i64(&bytes));
+        }
+    }
+
+    #[test]
+    fn test_isqrt() {
+        for (n, root) in [
+            (0, 0),
+            (1, 1),
+            (3, 1),
+            (4, 2),
+            (15, 3),
+            (16, 4),
+            (99, 9),
+            (100, 10),
+        ] {
+            assert_eq!(root, isqrt(n));
+        }
+        assert_eq!(u32::MAX as u64, isqrt(u64::MAX));
     }
 }